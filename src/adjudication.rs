@@ -0,0 +1,185 @@
+use chess_state::*;
+use game::*;
+use settings::*;
+use shakmaty::*;
+use tree_node::*;
+
+// level scores in the opening don't mean the game is drawn
+const MIN_MOVES_BEFORE_DRAW_OFFER: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Play,
+    OfferDraw, // and play
+    Resign,
+}
+
+// decides when to give up or offer a draw, from our score over the last few moves
+#[derive(Debug, Default)]
+pub struct Adjudication {
+    losing_moves: u32,
+    level_moves: u32,
+    last_score: Option<Reward>,
+}
+
+impl Adjudication {
+    // called after each search for our move, with the score relative to us
+    pub fn decide(&mut self, score: Reward, fullmoves: u32, settings: &Settings) -> Decision {
+        self.last_score = Some(score);
+        if settings.resign_score.is_some_and(|s| score <= s) {
+            self.losing_moves += 1;
+        } else {
+            self.losing_moves = 0;
+        }
+        if settings.draw_score.is_some_and(|s| score.abs() <= s) {
+            self.level_moves += 1;
+        } else {
+            self.level_moves = 0;
+        }
+
+        if self.losing_moves >= settings.resign_moves {
+            Decision::Resign
+        } else if fullmoves >= MIN_MOVES_BEFORE_DRAW_OFFER && self.level_moves >= settings.draw_moves
+        {
+            // wait another N moves before offering again
+            self.level_moves = 0;
+            Decision::OfferDraw
+        } else {
+            Decision::Play
+        }
+    }
+
+    // we take a draw when we're no better than a score we'd offer one at, and never without a
+    // draw score, so the GUI or the player decides
+    pub fn accepts_draw(&self, settings: &Settings) -> bool {
+        match (self.last_score, settings.draw_score) {
+            (Some(score), Some(draw_score)) => score <= draw_score,
+            _ => false,
+        }
+    }
+}
+
+// the result xboard expects us to claim when the game is over on the board. Variants can end
+// without a mate, e.g. with a king on the hill, or with the stalemated side winning
+pub fn claim_result<P: Game>(root: &TreeNode<ChessState<P>>) -> Option<&'static str> {
+    let position = &root.game.position;
+    let outcome = position.outcome();
+    if position.is_checkmate() {
+        Some(match position.turn() {
+            Color::Black => "1-0 {White mates}",
+            Color::White => "0-1 {Black mates}",
+        })
+    } else if let Some(Outcome::Decisive { winner }) = outcome {
+        Some(match winner {
+            Color::White => "1-0 {White wins}",
+            Color::Black => "0-1 {Black wins}",
+        })
+    } else if outcome.is_some() && position.is_stalemate() {
+        Some("1/2-1/2 {Stalemate}")
+    } else if outcome.is_some() {
+        Some("1/2-1/2 {Insufficient material}")
+    } else if root.game.repetition_detector.is_drawn(position) {
+        Some("1/2-1/2 {Draw by repetition}")
+    } else if position.halfmoves() >= MAX_HALFMOVES {
+        Some("1/2-1/2 {Draw by 50 move rule}")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use setup::*;
+
+    fn settings() -> Settings {
+        Settings {
+            resign_score: Some(-1000),
+            resign_moves: 3,
+            draw_score: Some(10),
+            draw_moves: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resigns_after_sustained_losing_scores() {
+        let settings = settings();
+        let mut adjudication: Adjudication = Default::default();
+        assert_eq!(adjudication.decide(-1200, 20, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-1100, 21, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-500, 22, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-1200, 23, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-1200, 24, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-1200, 25, &settings), Decision::Resign);
+    }
+
+    #[test]
+    fn never_resigns_without_threshold() {
+        let settings = Settings {
+            resign_score: None,
+            ..settings()
+        };
+        let mut adjudication: Adjudication = Default::default();
+        for fullmoves in 0..10 {
+            assert_eq!(
+                adjudication.decide(MIN_REWARD, fullmoves, &settings),
+                Decision::Play
+            );
+        }
+    }
+
+    #[test]
+    fn offers_draws_after_the_opening() {
+        let settings = settings();
+        let mut adjudication: Adjudication = Default::default();
+        assert_eq!(adjudication.decide(0, 10, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(5, 11, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(-5, 40, &settings), Decision::OfferDraw);
+        assert_eq!(adjudication.decide(0, 41, &settings), Decision::Play);
+        assert_eq!(adjudication.decide(0, 42, &settings), Decision::OfferDraw);
+    }
+
+    #[test]
+    fn accepts_draws_unless_winning() {
+        let settings = settings();
+        let mut adjudication: Adjudication = Default::default();
+        assert!(!adjudication.accepts_draw(&settings));
+        adjudication.decide(-300, 10, &settings);
+        assert!(adjudication.accepts_draw(&settings));
+        adjudication.decide(300, 11, &settings);
+        assert!(!adjudication.accepts_draw(&settings));
+    }
+
+    #[test]
+    fn never_accepts_draws_without_threshold() {
+        let settings = Settings {
+            draw_score: None,
+            ..settings()
+        };
+        let mut adjudication: Adjudication = Default::default();
+        adjudication.decide(-300, 10, &settings);
+        assert!(!adjudication.accepts_draw(&settings));
+    }
+
+    #[test]
+    fn claims_results() {
+        let root = |fen| TreeNode::new_root(ChessState::new(parse_fen(fen)));
+        let mated = root("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(claim_result(&mated), Some("1-0 {White mates}"));
+        let stalemate = root("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(claim_result(&stalemate), Some("1/2-1/2 {Stalemate}"));
+        let bare_kings = root("7k/8/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(
+            claim_result(&bare_kings),
+            Some("1/2-1/2 {Insufficient material}")
+        );
+        let fifty_moves = root("7k/8/6K1/8/8/8/8/R7 b - - 100 80");
+        assert_eq!(
+            claim_result(&fifty_moves),
+            Some("1/2-1/2 {Draw by 50 move rule}")
+        );
+        let playing = root(STARTING_POSITION);
+        assert_eq!(claim_result(&playing), None);
+    }
+}
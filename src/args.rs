@@ -1,3 +1,5 @@
+use move_selection::*;
+use settings::*;
 use setup::*;
 use shakmaty::*;
 use std::env;
@@ -32,3 +34,18 @@ pub fn parse_starting_position() -> Chess {
 pub fn parse_log_level() -> String {
     env::var("LOG_LEVEL").unwrap_or("info".to_string())
 }
+
+pub fn parse_pgn_file() -> Option<String> {
+    env::var("PGN_FILE").ok()
+}
+
+// e.g. FINAL_MOVE_SELECTION=max-robust,minimax to compare policies on the test positions. An
+// invalid chain is logged and the settings are left alone
+pub fn apply_final_move_selection_env(settings: &mut Settings) {
+    if let Ok(chain) = env::var("FINAL_MOVE_SELECTION") {
+        match parse_final_move_selection(&chain) {
+            Ok(chain) => settings.final_move_selection = chain,
+            Err(err) => error!("ignoring FINAL_MOVE_SELECTION: {}", err),
+        }
+    }
+}
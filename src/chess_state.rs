@@ -0,0 +1,190 @@
+use eval::*;
+use game::*;
+use game_state::*;
+use hash::*;
+use playout::*;
+use prior::*;
+use repetition_detector::*;
+use settings::*;
+use shakmaty::*;
+use stats::*;
+use std::mem;
+use variant::*;
+
+// chess as the search sees it: a position, the history needed to detect repetitions and the
+// position's Zobrist hash, which is kept up to date as moves are applied
+#[derive(Debug, Clone)]
+pub struct ChessState<P = VariantPosition> {
+    pub position: P,
+    pub repetition_detector: RepetitionDetector,
+    pub hash: Hash,
+}
+
+impl<P: Game> Default for ChessState<P> {
+    fn default() -> ChessState<P> {
+        ChessState::new(Default::default())
+    }
+}
+
+impl<P: Game> ChessState<P> {
+    pub fn new(position: P) -> ChessState<P> {
+        let repetition_detector = RepetitionDetector::new(&position);
+        ChessState::with_detector(position, repetition_detector)
+    }
+
+    pub fn with_detector(position: P, repetition_detector: RepetitionDetector) -> ChessState<P> {
+        let hash = Hash::generate(&position);
+        ChessState {
+            position,
+            repetition_detector,
+            hash,
+        }
+    }
+
+    pub fn turn(&self) -> Color {
+        self.position.turn()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.position.is_game_over()
+            || self.position.halfmoves() == MAX_HALFMOVES
+            || self.repetition_detector.is_drawn(&self.position)
+    }
+
+    // stalemate isn't always a draw, e.g. it wins antichess, so we go by the variant's outcome
+    pub fn is_drawn(&self) -> bool {
+        self.position.halfmoves() == MAX_HALFMOVES
+            || self.repetition_detector.is_drawn(&self.position)
+            || self.position.outcome() == Some(Outcome::Draw)
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.is_drawn() {
+            Some(Outcome::Draw)
+        } else {
+            self.position.outcome()
+        }
+    }
+}
+
+impl From<Color> for Player {
+    fn from(color: Color) -> Player {
+        color.fold(Player::First, Player::Second)
+    }
+}
+
+impl<P: Game> GameState for ChessState<P> {
+    type Action = Move;
+
+    fn legal_actions(&self) -> Vec<Move> {
+        if self.is_game_over() {
+            vec![]
+        } else {
+            self.position.legals().to_vec()
+        }
+    }
+
+    fn apply(&mut self, action: &Move) {
+        let before = self.position.clone();
+        self.position.make_move(action);
+        self.hash.play(&before, action, &self.position);
+        self.repetition_detector.record(&self.position);
+    }
+
+    fn player_to_move(&self) -> Player {
+        Player::from(self.turn())
+    }
+
+    fn terminal_value(&self) -> Option<Reward> {
+        if self.is_game_over() {
+            self.outcome().map(|o| o.reward())
+        } else {
+            None
+        }
+    }
+
+    fn heuristic_value(&self) -> Reward {
+        self.position.reward()
+    }
+
+    fn action_score(&self, action: &Move) -> f32 {
+        move_score(&self.position, action)
+    }
+
+    fn evaluate(&self, stats: &mut RunStats, settings: &Settings) -> Reward {
+        playout(self.position.clone(), stats, settings)
+    }
+
+    fn evaluate_line(&self, stats: &mut RunStats, settings: &Settings) -> (Reward, Vec<Move>) {
+        playout_line(self.position.clone(), stats, settings)
+    }
+
+    // a position already repeated in the history, or a halfmove clock well on the way to the 50
+    // move rule, would draw lines below the position that aren't drawn from its transpositions.
+    // Pockets aren't hashed
+    fn transposition_key(&self) -> Option<u64> {
+        if self.repetition_detector.has_repetition()
+            || self.position.halfmoves() >= MAX_HALFMOVES / 2
+            || self.position.pockets().is_some()
+        {
+            None
+        } else {
+            Some(self.hash.val)
+        }
+    }
+
+    // a position seen before can be drawn by repeating it, wherever else it's reached from
+    fn depends_on_history(&self) -> bool {
+        self.position.halfmoves() == MAX_HALFMOVES
+            || self.repetition_detector.is_repeated(&self.position)
+    }
+
+    // every node carries its own repetition detector, which is most of its size
+    fn estimated_size(&self) -> usize {
+        mem::size_of::<ChessState<P>>() + self.repetition_detector.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use setup::*;
+    use utils::*;
+
+    fn play(ucis: &[&str]) -> ChessState {
+        let mut state: ChessState = Default::default();
+        for uci in ucis {
+            let action = parse_uci(uci, &state.position);
+            state.apply(&action);
+        }
+        state
+    }
+
+    #[test]
+    fn keeps_the_hash_up_to_date_in_every_variant() {
+        let mut rng = seeded_rng(Settings::test_default().starting_seed);
+        for variant in VARIANTS.iter() {
+            for _ in 0..20 {
+                let mut state: ChessState = ChessState::new(variant.starting_position());
+                let mut actions = state.legal_actions();
+                while !actions.is_empty() {
+                    state.apply(choose_random(&mut rng, &actions));
+                    assert_eq!(state.hash, Hash::generate(&state.position), "{:?}", variant);
+                    actions = state.legal_actions();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transposes_until_a_position_repeats() {
+        let key = play(&["e2e3", "e7e6", "d2d3"]).transposition_key();
+        assert!(key.is_some());
+        assert_eq!(play(&["d2d3", "e7e6", "e2e3"]).transposition_key(), key);
+
+        let back_again = play(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(back_again.depends_on_history());
+        assert_eq!(back_again.transposition_key(), None);
+        assert!(!play(&["g1f3", "g8f6"]).depends_on_history());
+    }
+}
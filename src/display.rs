@@ -1,5 +1,6 @@
 use emojify::DisplayEmojify;
 use engine::*;
+use game::*;
 use log::*;
 use node::*;
 use pad::PadStr;
@@ -57,7 +58,9 @@ impl fmt::Display for RunStats {
              MCTS:     depth: {}, nodes {}, iterations: {}, leaves: {}\n\
              PLAYOUT:  depth: {}  leaves:  {}\n\
              Q SEARCH: depth: {}  {} %q,  \n\
-             EVALS:    {}     {} e/s",
+             EVALS:    {}     {} e/s\n\
+             PONDER:   hits: {}  misses: {}\n\
+             TT:       probes: {}  hits: {} %",
             self.elapsed(),
             self.mcts_max_depth,
             self.nodes_created.separated_string(),
@@ -69,6 +72,10 @@ impl fmt::Display for RunStats {
             self.q_percent(),
             self.evals.separated_string(),
             self.evals_per_second().separated_string(),
+            self.ponder_hits,
+            self.ponder_misses,
+            self.tt_probes.separated_string(),
+            self.tt_hit_rate(),
         )
     }
 }
@@ -119,7 +126,7 @@ impl<'a> fmt::Display for DisplayTreeNode<'a> {
                 Some(a) => writeln!(
                     f,
                     "{}. {} {} q={} n={} m={} v={} w={}",
-                    node.display_move_num(),
+                    node.game.position.display_move_num(),
                     a.to_string().pad_to_width(7),
                     node.state,
                     node.q.to_string().pad_to_width(16),
@@ -131,7 +138,7 @@ impl<'a> fmt::Display for DisplayTreeNode<'a> {
                 None => writeln!(
                     f,
                     "{}. Root {} q={} n={} m={} v={}",
-                    node.display_move_num(),
+                    node.game.position.display_move_num(),
                     node.state,
                     node.q.to_string().pad_to_width(16),
                     node.n.to_string().pad_to_width(5),
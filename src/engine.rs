@@ -1,21 +1,43 @@
 use display::*;
+use error::*;
 use game::*;
+use history::*;
+use options;
+use pgn::*;
 use search_strategy::*;
 use settings::*;
 use setup::*;
 use shakmaty::*;
 use state::*;
 use stats::*;
+use time_control::*;
+use tree_node::*;
+use variant::*;
 use std::mem;
-use std::time::Duration;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+pub const ENGINE_NAME: &str = "deadbeef";
+
+// a search running on its own thread until it finishes or is stopped. The tree is moved into the
+// thread, leaving a childless copy of the root so the position can still be read. Anything that
+// changes the position stops the search first. Clock updates can still be made
+pub struct BackgroundSearch {
+    stop_signal: StopSignal,
+    handle: JoinHandle<(TreeNode, RunStats)>,
+}
 
 #[derive(Default)]
 pub struct Engine {
     pub state: State,
     pub color: Option<Color>,
-    pub previous_position: Chess, // we need this after making a move so we can generate Uci
+    pub previous_position: VariantPosition, // we need this after making a move so we can generate Uci
     pub game_stats: RunStats,
     pub settings: Settings,
+    pub time_control: Option<TimeControl>,
+    pub history: GameHistory,
+    pub background_search: Option<BackgroundSearch>,
 }
 
 impl Engine {
@@ -27,21 +49,46 @@ impl Engine {
         }
     }
 
+    // the starting position of the variant being played
     pub fn reset(&mut self) {
-        self.set_board(STARTING_POSITION).unwrap();
+        let position = self.settings.variant.starting_position();
+        self.set_position(position);
+    }
+
+    pub fn set_board(&mut self, fen_str: &str) -> Result<(), EngineError> {
+        let position = self.settings.variant.position_from_fen(fen_str)?;
+        self.set_position(position);
+        Ok(())
     }
 
-    pub fn set_board(&mut self, fen_str: &str) -> Result<(), String> {
-        parse_fen_input(fen_str).map(|position| {
-            self.state = State::from_position(position);
-            self.game_stats = Default::default();
-            info!("{}", self);
-        })
+    fn set_position(&mut self, position: VariantPosition) {
+        self.stop_background_search();
+        self.history = GameHistory::new(position.clone());
+        self.state = State::from_position(position);
+        self.game_stats = Default::default();
+        // the hash doesn't tell variants apart
+        if let Some(ref table) = self.settings.transpositions {
+            table.clear();
+        }
+        if let Some(ref table) = self.settings.playout_table {
+            table.clear();
+        }
+        info!("{}", self);
     }
 
-    pub fn make_user_move(&mut self, uci_str: &str) -> Result<Move, String> {
+    // takes effect from the next new game or position
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.settings.variant = variant;
+    }
+
+    pub fn make_user_move(&mut self, uci_str: &str) -> Result<Move, EngineError> {
         info!("=========  user ==========");
+        let pondered = self.stop_background_search().is_some();
         let action = parse_uci_input(uci_str, &self.position())?;
+        if pondered {
+            self.record_ponder_result(&action);
+        }
+        self.history.record(&self.state.root, &action);
         self.change_state(|s| s.make_move(&action));
         debug_print_tree(&self.state.root, &self.settings);
         info!("{}", self);
@@ -49,15 +96,40 @@ impl Engine {
         Ok(action)
     }
 
-    pub fn make_engine_move(&mut self) -> Result<Move, String> {
+    pub fn make_engine_move(&mut self) -> Result<Move, EngineError> {
         info!("++++++++++ engine ++++++++++");
-        let search_type = &self.settings.search_type.clone();
+        let search_type = &self.search_type_for_move();
         self.search(search_type)?;
+        Ok(self.play_best_move())
+    }
+
+    pub fn play_best_move(&mut self) -> Move {
         let best_move = self.best_move();
+        self.history.record(&self.state.root, &best_move);
         self.change_state(|s| s.make_move(&best_move));
         info!("{}", self);
         info!("+++++++++++++++++++++++++++");
-        Ok(best_move)
+        best_move
+    }
+
+    // takes back the last ply, e.g. for xboard's undo
+    pub fn undo(&mut self) -> Result<(), EngineError> {
+        self.undo_n(1)
+    }
+
+    // takes back the last n plies, or none if there aren't that many, e.g. 2 for xboard's remove
+    pub fn undo_n(&mut self, n: usize) -> Result<(), EngineError> {
+        self.stop_background_search();
+        if self.history.len() < n {
+            return Err(EngineError::NotLegalNow("no moves to undo".to_string()));
+        }
+        let mut history = mem::take(&mut self.history);
+        for _ in 0..n {
+            self.change_state(|s| s.take_back(&mut history));
+        }
+        self.history = history;
+        info!("{}", self);
+        Ok(())
     }
 
     pub fn test_search(&mut self, search_type: &SearchType) -> Move {
@@ -66,7 +138,7 @@ impl Engine {
         self.best_move()
     }
 
-    pub fn position(&self) -> Chess {
+    pub fn position(&self) -> VariantPosition {
         self.state.position()
     }
 
@@ -80,10 +152,56 @@ impl Engine {
         self.change_state(|s| s.set_opponent_time_remaining(remaining));
     }
 
+    pub fn set_ponder(&mut self, ponder: bool) {
+        self.settings.ponder = ponder;
+    }
+
+    pub fn set_time_control(&mut self, time_control: TimeControl) {
+        self.time_control = Some(time_control);
+    }
+
+    pub fn set_memory_mb(&mut self, memory_mb: u64) {
+        self.settings.memory_mb = Some(memory_mb);
+    }
+
+    pub fn set_max_threads(&mut self, max_threads: u16) {
+        self.settings.max_threads = max_threads.max(1);
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.settings.max_depth = Some(max_depth);
+    }
+
+    // the GUI's time control takes precedence over the search type in Settings
+    pub fn search_type_for_move(&self) -> SearchType {
+        match self.time_control {
+            Some(ref time_control) => {
+                let time_remaining = self.state.time_remaining.as_ref().map(|t| t.remaining());
+                let time = time_control.time_for_move(time_remaining, self.moves_played());
+                SearchType::Time(time)
+            }
+            None => self.settings.search_type.clone(),
+        }
+    }
+
+    // full moves played since the game started, which is how many moves of the time control
+    // we've used
+    pub fn moves_played(&self) -> u32 {
+        self.position().fullmoves() - self.history.start_position.fullmoves()
+    }
+
     pub fn set_show_thinking(&mut self, show_thinking: bool) {
         self.settings.show_thinking = show_thinking;
     }
 
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), EngineError> {
+        options::set_option(&mut self.settings, name, value).map_err(EngineError::Parse)
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.settings.protocol = protocol;
+    }
+
     pub fn set_color(&mut self, color: Color) {
         self.color = Some(color);
     }
@@ -100,19 +218,19 @@ impl Engine {
         self.state.is_checkmate()
     }
 
-    pub fn print_tree(&self) -> Result<(), String> {
+    pub fn print_tree(&self) -> Result<(), EngineError> {
         self.print_subtree(vec![])
     }
 
     pub fn best_move(&self) -> Move {
-        self.state.best_move()
+        self.state.best_move(&self.settings)
     }
 
     pub fn minimax(&self) -> Reward {
         self.state.minimax()
     }
 
-    pub fn print_subtree(&self, action_uci_strs: Vec<&str>) -> Result<(), String> {
+    pub fn print_subtree(&self, action_uci_strs: Vec<&str>) -> Result<(), EngineError> {
         let mut root = &self.state.root;
         let mut position = self.state.position();
         for uci_str in action_uci_strs {
@@ -121,31 +239,174 @@ impl Engine {
                 .children
                 .iter()
                 .find(|c| c.action.clone().unwrap() == action)
-                .ok_or("could not find child")?;
-            position = position.play(&action).map_err(|e| e.to_string())?;
+                .ok_or(EngineError::NotLegalNow(format!("no child for {}", uci_str)))?;
+            position = position
+                .play(&action)
+                .map_err(|_| EngineError::IllegalMove(uci_str.to_string()))?;
         }
         info_print_tree(&root, &self.settings);
         Ok(())
     }
 
-    pub fn search_with_settings(&mut self) -> Result<(), String> {
+    pub fn search_with_settings(&mut self) -> Result<(), EngineError> {
         let search_type = &self.settings.search_type.clone();
         self.search(search_type)
     }
 
-    pub fn search(&mut self, search_type: &SearchType) -> Result<(), String> {
+    pub fn search(&mut self, search_type: &SearchType) -> Result<(), EngineError> {
         if self.is_game_over() {
-            return Err("game is over".to_string());
+            return Err(EngineError::GameOver);
         }
         let mut move_run_stats: RunStats = Default::default();
         let settings = self.settings.clone();
-        self.change_state(|s| s.search(search_type.clone(), &mut move_run_stats, &settings));
+        let stop_signal: StopSignal = Default::default();
+        self.change_state(|s| {
+            s.search(
+                search_type.clone(),
+                &mut move_run_stats,
+                &settings,
+                &stop_signal,
+            )
+        });
         debug_print_tree(&self.state.root, &self.settings);
         info!("{}", move_run_stats);
         self.game_stats.add(&move_run_stats);
         Ok(())
     }
 
+    pub fn start_background_search(&mut self) -> Result<(), EngineError> {
+        let settings = self.settings.clone();
+        self.start_background_search_with(SearchType::Ponder, settings)
+    }
+
+    // searches for our own move in the background, so the GUI can still be answered. The move is
+    // played with play_best_move once the search has finished or been stopped
+    pub fn start_thinking(&mut self) -> Result<(), EngineError> {
+        let search_type = self.search_type_for_move();
+        self.start_thinking_with(search_type)
+    }
+
+    // e.g. for UCI's go, which sends the limits of each search with it
+    pub fn start_thinking_with(&mut self, search_type: SearchType) -> Result<(), EngineError> {
+        let settings = self.settings.clone();
+        self.start_background_search_with(search_type, settings)
+    }
+
+    // analysis always shows its thinking, that's the point of it
+    pub fn start_analysis(&mut self) -> Result<(), EngineError> {
+        let settings = Settings {
+            show_thinking: true,
+            ..self.settings.clone()
+        };
+        self.start_background_search_with(SearchType::Ponder, settings)
+    }
+
+    // searches the opponent's replies while we wait for their move
+    pub fn start_pondering(&mut self) -> Result<(), EngineError> {
+        let settings = Settings {
+            show_thinking: false,
+            ..self.settings.clone()
+        };
+        self.start_background_search_with(SearchType::Ponder, settings)
+    }
+
+    fn start_background_search_with(
+        &mut self,
+        search_type: SearchType,
+        settings: Settings,
+    ) -> Result<(), EngineError> {
+        if self.is_game_over() {
+            return Err(EngineError::GameOver);
+        }
+        self.stop_background_search();
+        let stop_signal: StopSignal = Default::default();
+        let thread_stop_signal = stop_signal.clone();
+        let copy = self.state.root.clone_childless();
+        let state = State {
+            root: mem::replace(&mut self.state.root, copy),
+            ..Default::default()
+        };
+        let handle = thread::spawn(move || {
+            let mut run_stats: RunStats = Default::default();
+            let new_state =
+                state.search(search_type, &mut run_stats, &settings, &thread_stop_signal);
+            (new_state.root, run_stats)
+        });
+        self.background_search = Some(BackgroundSearch {
+            stop_signal,
+            handle,
+        });
+        Ok(())
+    }
+
+    // returns the stats of the search, or None if there was no search to stop
+    pub fn stop_background_search(&mut self) -> Option<RunStats> {
+        match self.background_search.take() {
+            Some(background_search) => {
+                background_search.stop_signal.stop();
+                let (root, run_stats) = background_search
+                    .handle
+                    .join()
+                    .expect("panicked joining background search");
+                self.state.root = root;
+                debug_print_tree(&self.state.root, &self.settings);
+                info!("{}", run_stats);
+                self.game_stats.add(&run_stats);
+                Some(run_stats)
+            }
+            None => None,
+        }
+    }
+
+    pub fn is_searching_in_background(&self) -> bool {
+        self.background_search.is_some()
+    }
+
+    // a timed search ends on its own; stop_background_search still has to be called to get the
+    // tree back
+    pub fn is_background_search_finished(&self) -> bool {
+        self.background_search
+            .as_ref()
+            .is_some_and(|b| b.handle.is_finished())
+    }
+
+    // a hit means the opponent played a move we already searched, so we keep that subtree
+    fn record_ponder_result(&mut self, action: &Move) {
+        let hit = self
+            .state
+            .root
+            .children
+            .iter()
+            .any(|c| c.action.as_ref() == Some(action) && c.n > 0);
+        if hit {
+            self.game_stats.ponder_hits += 1;
+        } else {
+            self.game_stats.ponder_misses += 1;
+        }
+        info!("ponder {}", if hit { "hit" } else { "miss" });
+    }
+
+    // the game so far, from the position set by new or setboard
+    pub fn pgn(&self, game_info: &GameInfo) -> String {
+        to_full_pgn(
+            ENGINE_NAME,
+            game_info,
+            self.time_control.as_ref(),
+            &pgn_date(SystemTime::now()),
+            &self.history.start_position,
+            self.settings.chess960,
+            &self.history.moves(),
+        )
+    }
+
+    // appends the game to Settings::pgn_file, if there is one
+    pub fn save_pgn(&self, game_info: &GameInfo) -> Result<(), EngineError> {
+        match self.settings.pgn_file {
+            Some(ref path) => append_pgn(path, &self.pgn(game_info)).map_err(EngineError::Io),
+            None => Ok(()),
+        }
+    }
+
     pub fn record_test_repetitions(&mut self, repetitions: Vec<&'static str>) {
         let repetition_positions: Vec<Chess> = repetitions.iter().map(|r| parse_fen(r)).collect();
         self.state.record_test_repetitions(repetition_positions);
@@ -0,0 +1,27 @@
+use std::fmt;
+
+// what can go wrong running a command. Each front-end renders these the way its protocol requires
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    IllegalMove(String),     // the move as the GUI sent it
+    InvalidPosition(String), // why the FEN couldn't be set up
+    Parse(String),           // what was wrong with the command's arguments
+    NotLegalNow(String),     // a valid command we can't run in this state, e.g. undo at the start
+    GameOver,
+    UnknownCommand,
+    Io(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EngineError::IllegalMove(ref action) => write!(f, "illegal move {}", action),
+            EngineError::InvalidPosition(ref reason) => write!(f, "invalid position: {}", reason),
+            EngineError::Parse(ref msg) => write!(f, "{}", msg),
+            EngineError::NotLegalNow(ref msg) => write!(f, "{}", msg),
+            EngineError::GameOver => write!(f, "game is over"),
+            EngineError::UnknownCommand => write!(f, "unknown command"),
+            EngineError::Io(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
@@ -100,26 +100,29 @@ impl HasReward for Piece {
     }
 }
 
+// the piece-square table bonus for a piece standing on a square, white positive
+pub fn positional_reward(square: Square, piece: &Piece) -> i16 {
+    let color_square = match piece.color {
+        Color::White => square.flip_vertical(),
+        Color::Black => square,
+    };
+    let raw_reward = match piece.role {
+        Role::Pawn => PAWN_VALUES[index(color_square)],
+        Role::Knight => KNIGHT_VALUES[index(color_square)],
+        Role::Bishop => BISHOP_VALUES[index(color_square)],
+        Role::Queen => QUEEN_VALUES[index(color_square)],
+        Role::Rook => ROOK_VALUES[index(color_square)],
+        Role::King => KING_VALUES[index(color_square)],
+    };
+    piece.color.coefficient() as i16 * raw_reward
+}
+
+fn index(square: Square) -> usize {
+    square as usize
+}
+
 impl HasReward for Board {
     fn reward(&self) -> i16 {
-        fn positional_reward(square: Square, piece: &Piece) -> i16 {
-            let color_square = match piece.color {
-                Color::White => square.flip_vertical(),
-                Color::Black => square,
-            };
-            let raw_reward = match piece.role {
-                Role::Pawn => PAWN_VALUES[index(color_square)],
-                Role::Knight => KNIGHT_VALUES[index(color_square)],
-                Role::Bishop => BISHOP_VALUES[index(color_square)],
-                Role::Queen => QUEEN_VALUES[index(color_square)],
-                Role::Rook => ROOK_VALUES[index(color_square)],
-                Role::King => KING_VALUES[index(color_square)],
-            };
-            piece.color.coefficient() as i16 * raw_reward
-        }
-        fn index(square: Square) -> usize {
-            square as usize
-        }
         self.pieces()
             .into_iter()
             .fold(0, |reward, (square, piece)| {
@@ -1,5 +1,6 @@
 use eval::*;
 use shakmaty::*;
+use std::fmt::Debug;
 use std::i16;
 use std::ops::Not;
 
@@ -10,16 +11,60 @@ pub type Reward = i16;
 pub const MAX_REWARD: Reward = i16::MAX;
 pub const MIN_REWARD: Reward = i16::MIN + 1; // for some reason, min is -32768 but max is 32767. The + 1 prevents overflows when we flip signs
 
-pub trait Game: Clone {
+// a game that's over scores MAX_REWARD or MIN_REWARD, less a point for every ply it takes to get
+// there, so the nearest win and the furthest loss score best. Evals never get this close
+pub const MAX_MATE_PLIES: Reward = 1000;
+
+pub fn is_mate_reward(reward: Reward) -> bool {
+    mate_plies(reward).is_some()
+}
+
+// how many plies until the game is won or lost
+pub fn mate_plies(reward: Reward) -> Option<Reward> {
+    if reward > MAX_REWARD - MAX_MATE_PLIES {
+        Some(MAX_REWARD - reward)
+    } else if reward < MIN_REWARD + MAX_MATE_PLIES {
+        Some(reward - MIN_REWARD)
+    } else {
+        None
+    }
+}
+
+// the reward of a position a ply before one scored reward
+pub fn backed_up(reward: Reward) -> Reward {
+    if reward > MAX_REWARD - MAX_MATE_PLIES {
+        reward - 1
+    } else if reward < MIN_REWARD + MAX_MATE_PLIES {
+        reward + 1
+    } else {
+        reward
+    }
+}
+
+// the other way round, e.g. for the alpha-beta window of a child
+pub fn pushed_down(reward: Reward) -> Reward {
+    if reward > MAX_REWARD - MAX_MATE_PLIES {
+        reward.saturating_add(1)
+    } else if reward < MIN_REWARD + MAX_MATE_PLIES {
+        (reward - 1).max(MIN_REWARD)
+    } else {
+        reward
+    }
+}
+
+// the positions the tree, playout and eval can search: standard chess, any other shakmaty variant,
+// or a VariantPosition picking one at runtime
+pub trait Game: Position + Clone + Default + Debug {
     fn allowed_actions(&self) -> Vec<Move>;
     fn make_move(&mut self, action: &Move);
     fn play_safe(&mut self, &Move);
     fn display_move_num(&self) -> String;
-    fn clone_and_play(&self, action: &Move) -> Chess;
+    fn clone_and_play(&self, action: &Move) -> Self;
     fn color_relative_reward(&self) -> i16;
+    fn reward(&self) -> Reward;
 }
 
-impl Game for Chess {
+impl<P: Position + Clone + Default + Debug> Game for P {
     // TODO this would probably be faster if didn't create the whoe list up front,
     // but rather iterated through it
     fn allowed_actions(&self) -> Vec<Move> {
@@ -61,7 +106,7 @@ impl Game for Chess {
         )
     }
 
-    fn clone_and_play(&self, action: &Move) -> Chess {
+    fn clone_and_play(&self, action: &Move) -> P {
         let mut new_position = self.clone();
         new_position.make_move(action);
         new_position
@@ -70,10 +115,9 @@ impl Game for Chess {
     fn color_relative_reward(&self) -> i16 {
         self.turn().not().coefficient() * self.reward()
     }
-}
 
-impl HasReward for Chess {
-    fn reward(&self) -> i16 {
+    // the outcome is variant aware, e.g. a king on the hill wins, but the board is scored as chess
+    fn reward(&self) -> Reward {
         match self.outcome() {
             Some(o) => o.reward(),
             None => self.board().reward(),
@@ -0,0 +1,89 @@
+use game::*;
+use settings::*;
+use stats::*;
+use std::fmt::Debug;
+use std::mem;
+use std::ops::Not;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Player {
+    First, // white, in chess
+    Second,
+}
+
+impl Not for Player {
+    type Output = Player;
+
+    fn not(self) -> Player {
+        match self {
+            Player::First => Player::Second,
+            Player::Second => Player::First,
+        }
+    }
+}
+
+impl Coefficient for Player {
+    fn coefficient(&self) -> i16 {
+        match self {
+            Player::First => 1,
+            Player::Second => -1,
+        }
+    }
+}
+
+// what the MCTS search needs to know about a two player game. Values are from the first player's
+// point of view: MAX_REWARD is a win for them, MIN_REWARD a win for the second player
+pub trait GameState: Clone + Debug + Default {
+    type Action: Clone + Debug + PartialEq;
+
+    // none once the game is over
+    fn legal_actions(&self) -> Vec<Self::Action>;
+    fn apply(&mut self, action: &Self::Action);
+    fn player_to_move(&self) -> Player;
+    // None while the game is still being played
+    fn terminal_value(&self) -> Option<Reward>;
+    // a quick static guess, used to pick which child to expand first
+    fn heuristic_value(&self) -> Reward;
+
+    // a quick guess at how good an action is for the player taking it, from which the search
+    // shares out its priors. Every action looks the same unless the game knows better
+    fn action_score(&self, _action: &Self::Action) -> f32 {
+        0.
+    }
+
+    // the value a new leaf starts with, e.g. from a playout
+    fn evaluate(&self, _stats: &mut RunStats, _settings: &Settings) -> Reward {
+        self.heuristic_value()
+    }
+
+    // the value, and the actions the evaluation expects to follow, which RAVE learns from
+    fn evaluate_line(
+        &self,
+        stats: &mut RunStats,
+        settings: &Settings,
+    ) -> (Reward, Vec<Self::Action>) {
+        (self.evaluate(stats, settings), vec![])
+    }
+
+    // identifies the state, so what the search learns about it can be shared with its
+    // transpositions. None if the game has no key, or if the values below the state could depend
+    // on how it was reached
+    fn transposition_key(&self) -> Option<u64> {
+        None
+    }
+
+    // whether the state's value comes from the moves that led to it, e.g. a repetition, so it
+    // mustn't be shared with its transpositions
+    fn depends_on_history(&self) -> bool {
+        false
+    }
+
+    // for the tree's memory budget
+    fn estimated_size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.terminal_value().is_some()
+    }
+}
@@ -3,7 +3,7 @@ use rand::Rng;
 use settings::*;
 use shakmaty::*;
 use std::fmt;
-use std::sync::Once;
+use std::sync::{Once, OnceLock};
 use utils::*;
 
 pub const PAWN: u8 = 0;
@@ -13,11 +13,6 @@ pub const ROOK: u8 = 3 << 1;
 pub const QUEEN: u8 = 4 << 1;
 pub const KING: u8 = 5 << 1;
 
-pub const BK_CASTLE: u8 = 1;
-pub const WK_CASTLE: u8 = BK_CASTLE << WHITE;
-pub const BQ_CASTLE: u8 = 1 << 2;
-pub const WQ_CASTLE: u8 = BQ_CASTLE << WHITE;
-
 // bit masks for generating index
 pub const PIECE: u8 = 0b1110;
 pub const COLOR: u8 = 1;
@@ -26,13 +21,15 @@ pub const WHITE: u8 = COLOR;
 pub const BLACK: u8 = 0;
 
 static mut PIECE_KEYS: [u64; 64 * 6 * 2] = [0; 64 * 6 * 2];
-static mut CASTLE_KEYS: [u64; 16] = [0; 16];
+static mut CASTLE_KEYS: [u64; 64] = [0; 64]; // by rook square, so Chess960 rooks hash too
 static mut EP_KEYS: [u64; 8] = [0; 8];
 static mut COLOR_KEY: u64 = 0;
+static CHECK_KEYS: OnceLock<[u64; 2 * 4]> = OnceLock::new(); // by color and checks remaining, for three-check
 
 pub type CastlingRightsArray = [[Option<Square>; 2]; 2]; // used by shakmaty::Castles
 
 static INIT: Once = Once::new();
+const DEFAULT_KEY_SEED: u8 = 2; // the default starting seed
 
 fn set_random(arr: &mut [u64], rng: &mut SmallRng) {
     for elem in arr.iter_mut() {
@@ -41,12 +38,23 @@ fn set_random(arr: &mut [u64], rng: &mut SmallRng) {
 }
 
 pub unsafe fn init_hash_keys(settings: Settings) {
+    init_keys(settings.starting_seed);
+}
+
+// hashing before the keys are set would give every position the same hash, so Hash::generate
+// sets them itself if nothing has yet
+unsafe fn init_keys(seed: u8) {
     INIT.call_once(|| {
-        let mut rng = seeded_rng(settings.starting_seed);
+        let mut rng = seeded_rng(seed);
         set_random(&mut PIECE_KEYS, &mut rng);
         set_random(&mut CASTLE_KEYS, &mut rng);
         set_random(&mut EP_KEYS, &mut rng);
         COLOR_KEY = rng.gen();
+        CHECK_KEYS.get_or_init(|| {
+            let mut keys = [0; 2 * 4];
+            set_random(&mut keys, &mut rng);
+            keys
+        });
     });
 }
 
@@ -58,7 +66,9 @@ pub struct Hash {
 pub type Squares = [u8; 64];
 
 impl Hash {
-    pub fn generate(position: &Chess) -> Self {
+    // pockets aren't hashed, so crazyhouse positions with the same board share a hash
+    pub fn generate<P: Position>(position: &P) -> Self {
+        unsafe { init_keys(DEFAULT_KEY_SEED) };
         let mut hash: Hash = Default::default();
 
         for (square, piece) in position.board().pieces() {
@@ -66,12 +76,98 @@ impl Hash {
         }
         hash.set_castling(position.castles());
         hash.set_ep(position.ep_square());
+        hash.set_remaining_checks(position.remaining_checks());
         if position.turn() == Color::White {
             hash.flip_color();
         }
         hash
     }
 
+    // updates the hash of the position before the move to that of the position after it
+    pub fn play<P: Position>(&mut self, before: &P, action: &Move, after: &P) {
+        let taken =
+            before.board().occupied().count() as i32 - after.board().occupied().count() as i32;
+        if taken > action.is_capture() as i32 {
+            // an atomic explosion took more than the captured piece
+            *self = Hash::generate(after);
+            return;
+        }
+        self.move_pieces(before.turn(), before.ep_square(), action);
+        if before.castling_rights() != after.castling_rights() {
+            self.set_castling(before.castles());
+            self.set_castling(after.castles());
+        }
+        if before.remaining_checks() != after.remaining_checks() {
+            self.set_remaining_checks(before.remaining_checks());
+            self.set_remaining_checks(after.remaining_checks());
+        }
+        self.set_ep(after.ep_square()); // add ep if we just gained one
+    }
+
+    // moves the pieces, clears the en passant square the move was made with and flips the turn.
+    // Castling rights and the new en passant square are left to the caller
+    pub fn move_pieces(&mut self, turn: Color, ep_square: Option<Square>, action: &Move) {
+        let piece = Piece {
+            color: turn,
+            role: action.role(),
+        };
+        match action {
+            Move::Normal {
+                from,
+                capture,
+                to,
+                promotion,
+                ..
+            } => {
+                self.set_piece(*from, piece); // remove from current square
+                if let Some(captured) = capture {
+                    let piece = Piece {
+                        color: !turn,
+                        role: *captured,
+                    }; // TODO, does all this derefing have a performance cost?
+                    self.set_piece(*to, piece); // remove captured piece
+                }
+                if let Some(promoted) = promotion {
+                    let piece = Piece {
+                        color: turn,
+                        role: *promoted,
+                    };
+                    self.set_piece(*to, piece)
+                } else {
+                    self.set_piece(*to, piece); // add at new square
+                }
+            }
+            Move::EnPassant { from, to } => {
+                self.set_piece(*from, piece);
+                self.set_piece(*to, piece);
+                let captured_piece = Piece {
+                    color: !turn,
+                    role: Role::Pawn,
+                };
+                let captured_square = Square::from_coords(to.file(), from.rank());
+                self.set_piece(captured_square, captured_piece);
+            }
+            Move::Castle { king, rook } => {
+                let king_piece = Piece {
+                    color: turn,
+                    role: Role::King,
+                };
+                let rook_piece = Piece {
+                    color: turn,
+                    role: Role::Rook,
+                };
+                self.set_piece(*king, king_piece);
+                self.set_piece(*rook, rook_piece);
+                let castling_side = action.castling_side().unwrap();
+                self.set_piece(castling_side.king_to(turn), king_piece);
+                self.set_piece(castling_side.rook_to(turn), rook_piece);
+            }
+            _ => {}
+        };
+        self.set_ep(ep_square); // removes ep if we had it or noops
+        self.flip_color();
+    }
+
     pub fn set_piece(&mut self, sq: Square, piece: Piece) {
         let piece_rep = piece_rep(piece);
         let index = sq as usize
@@ -81,22 +177,9 @@ impl Hash {
     }
 
     pub fn set_castling(&mut self, castling: &Castles) {
-        let mut castle_key: u8 = 0;
-        if castling.has(Color::Black, CastlingSide::KingSide) {
-            castle_key |= BK_CASTLE
-        }
-        if castling.has(Color::Black, CastlingSide::QueenSide) {
-            castle_key |= BQ_CASTLE
-        }
-        if castling.has(Color::White, CastlingSide::KingSide) {
-            castle_key |= WK_CASTLE
+        for rook in castling.castling_rights() {
+            self.val ^= unsafe { CASTLE_KEYS[rook as usize] };
         }
-        if castling.has(Color::White, CastlingSide::QueenSide) {
-            castle_key |= WQ_CASTLE
-        }
-        unsafe {
-            self.val ^= CASTLE_KEYS[castle_key as usize];
-        }; // TODO will this work on 32 but systems?
     }
 
     pub fn set_ep(&mut self, en_passant: Option<Square>) {
@@ -109,12 +192,22 @@ impl Hash {
         }
     }
 
+    pub fn set_remaining_checks(&mut self, remaining_checks: Option<&RemainingChecks>) {
+        if let Some(checks) = remaining_checks {
+            for &(index, remaining) in &[(0, checks.white), (1, checks.black)] {
+                let keys = CHECK_KEYS.get().expect("hashing before the keys are set");
+                self.val ^= keys[index * 4 + remaining.min(3) as usize];
+            }
+        }
+    }
+
     pub fn flip_color(&mut self) {
         self.val ^= unsafe { COLOR_KEY };
     }
 
-    pub fn sub(&self) -> u16 {
-        (self.val >> 48) as u16
+    // the bits the table checks its entries against, apart from those indexing it
+    pub fn sub(&self) -> u32 {
+        (self.val >> 32) as u32
     }
 }
 
@@ -287,7 +380,7 @@ mod tests {
                             castling_rights: Bitboard::EMPTY,
                             ..Default::default()
                         };
-                        match Position::from_setup(&fen) {
+                        match Chess::from_setup(&fen) {
                             Ok(position) => {
                                 let hash = Hash::generate(&position).val;
                                 if seen.contains_key(&hash) {
@@ -0,0 +1,61 @@
+use mcts::*;
+use shakmaty::*;
+use tree_node::*;
+use variant::*;
+
+// a move played in the game, and the root it was played from, so it can be taken back
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub action: Move,
+    pub previous_root: TreeNode, // childless, but with the position and repetition counts
+}
+
+#[derive(Debug, Default)]
+pub struct GameHistory {
+    pub start_position: VariantPosition,
+    entries: Vec<HistoryEntry>,
+}
+
+impl GameHistory {
+    pub fn new(start_position: VariantPosition) -> GameHistory {
+        GameHistory {
+            start_position,
+            entries: vec![],
+        }
+    }
+
+    pub fn record(&mut self, previous_root: &TreeNode, action: &Move) {
+        self.entries.push(HistoryEntry {
+            action: action.clone(),
+            previous_root: TreeNode::new_root(previous_root.game.clone()),
+        });
+    }
+
+    // rebuilds the root from before the last move, keeping the current tree as its only child
+    pub fn take_back(&mut self, mut current_root: TreeNode) -> Option<TreeNode> {
+        self.entries.pop().map(|entry| {
+            let mut root = entry.previous_root;
+            current_root.action = Some(entry.action);
+            root.n = current_root.n;
+            root.q = current_root.q;
+            root.children.push(current_root);
+            if root.actions_with_no_children().is_empty() {
+                root.state = NodeState::FullyExpanded;
+                root.update_based_on_children();
+            }
+            root
+        })
+    }
+
+    pub fn moves(&self) -> Vec<Move> {
+        self.entries.iter().map(|e| e.action.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
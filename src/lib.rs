@@ -14,22 +14,31 @@ extern crate rand;
 extern crate separator;
 extern crate shakmaty;
 extern crate twox_hash;
+pub mod adjudication;
 pub mod args;
+pub mod chess_state;
 pub mod display;
 pub mod emojify;
 pub mod engine;
+pub mod error;
 pub mod eval;
 pub mod game;
+pub mod game_state;
 pub mod random_move; // TODO this is only for tests
 pub mod hash;
+pub mod history;
 pub mod logger;
 pub mod mcts;
+pub mod move_selection;
 pub mod node;
+pub mod options;
 pub mod pgn;
 pub mod play;
 pub mod playout;
+pub mod prior;
 pub mod q_search;
 pub mod repetition_detector;
+pub mod search_ensemble;
 pub mod search_iterations;
 pub mod search_ponder;
 pub mod search_strategy;
@@ -40,8 +49,13 @@ pub mod setup;
 pub mod show_thinking;
 pub mod state;
 pub mod stats;
+pub mod table;
+pub mod time_control;
 pub mod time_remaining;
+pub mod transposition;
 pub mod tree_node;
+pub mod uci;
 pub mod uct;
 pub mod utils;
+pub mod variant;
 pub mod xboard;
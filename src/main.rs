@@ -1,21 +1,38 @@
 extern crate deadbeef;
 extern crate log;
 
+use deadbeef::args::*;
 use deadbeef::engine::*;
 use deadbeef::logger;
 use deadbeef::settings::Settings;
+use deadbeef::uci::Uci;
 use deadbeef::xboard::XBoard;
 use log::*;
+use std::io::{self, BufRead};
 
 //TODO remove Copy from Move in Shakmaty
 
 pub fn main() {
     logger::init();
 
-    let settings: Settings = Default::default();
+    let mut settings: Settings = Default::default();
+    apply_final_move_selection_env(&mut settings);
 
     let mut engine: Engine = Engine::new(settings);
-    let mut xboard: XBoard = Default::default();
-    xboard.start(&mut engine);
+
+    // the first command tells us which protocol the GUI speaks
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input).unwrap();
+    let first_cmd = input.trim();
+
+    if first_cmd == "uci" {
+        let mut uci: Uci = Default::default();
+        uci.handle_command(&mut engine, first_cmd);
+        uci.start(&mut engine);
+    } else {
+        let mut xboard: XBoard = Default::default();
+        xboard.handle_command(&mut engine, first_cmd);
+        xboard.start(&mut engine);
+    }
     warn!("exiting!");
 }
@@ -1,68 +1,134 @@
-use eval::*;
 use game::*;
-use playout::*;
+use game_state::*;
+use prior::*;
 use rand::rngs::SmallRng;
 use settings::*;
-use shakmaty::*;
 use stats::*;
+use std::cmp::Ordering::*;
 use std::f32;
+use transposition::*;
 use tree_node::*;
 use uct::*;
 
 pub trait MCTS {
+    type Action;
     fn iteration(&mut self, rng: &mut SmallRng, stats: &mut RunStats, settings: &Settings) -> f32;
-    fn expand(&self, candidate_actions: &Vec<Move>) -> TreeNode;
-    fn actions_with_no_children(&self) -> Vec<Move>;
+    fn iteration_with_line(
+        &mut self,
+        rng: &mut SmallRng,
+        stats: &mut RunStats,
+        settings: &Settings,
+        line: &mut Vec<Self::Action>,
+    ) -> f32;
+    fn backpropagate(&mut self, line: &[Self::Action], normalized_value: f32, settings: &Settings);
+    fn update_amaf(&mut self, line: &[Self::Action], normalized_value: f32);
+    fn adopt_transposition(&mut self, stats: &mut RunStats, settings: &Settings) -> Option<f32>;
+    fn refresh_from_transposition(&mut self, settings: &Settings);
+    fn store_transposition(&self, settings: &Settings);
+    fn expand(&mut self, settings: &Settings) -> Self;
+    fn replay(
+        &self,
+        stats: &mut RunStats,
+        settings: &Settings,
+        line: &mut Vec<Self::Action>,
+    ) -> f32;
+    fn next_unexpanded(&mut self, settings: &Settings) -> Option<(Self::Action, f32)>;
+    fn set_unexpanded(&mut self, by_promise: Vec<(Self::Action, f32)>);
+    fn is_fully_expanded(&self) -> bool;
+    fn action_priors(&self, settings: &Settings) -> Vec<(Self::Action, f32)>;
+    fn actions_by_promise(
+        &self,
+        actions: Vec<Self::Action>,
+        settings: &Settings,
+    ) -> Vec<(Self::Action, f32)>;
+    fn is_widened(&self, settings: &Settings) -> bool;
+    fn has_children_to_search(&self) -> bool;
+    fn actions_with_no_children(&self) -> Vec<Self::Action>;
     fn update_based_on_children(&mut self);
     fn update_root_based_on_children(&mut self);
+    fn set_bounds_for_game_in_play(&mut self);
+    fn cannot_mate_sooner(&self) -> bool;
     fn normalized_value(&self) -> f32;
     fn set_minimax_based_on_children(&mut self);
-    fn generate_missing_children(&mut self, stats: &mut RunStats);
+    fn set_minimax_based_on_widened_children(&mut self, settings: &Settings);
+    fn set_bounds_based_on_children(&mut self);
+    fn generate_missing_children(&mut self, stats: &mut RunStats, settings: &Settings);
 }
 
-impl MCTS for TreeNode {
+impl<S: GameState> MCTS for TreeNode<S> {
+    type Action = S::Action;
+
     fn iteration(&mut self, rng: &mut SmallRng, stats: &mut RunStats, settings: &Settings) -> f32 {
+        self.iteration_with_line(rng, stats, settings, &mut vec![])
+    }
+
+    // the line is filled with the actions played from this node on, in reverse, for RAVE
+    fn iteration_with_line(
+        &mut self,
+        rng: &mut SmallRng,
+        stats: &mut RunStats,
+        settings: &Settings,
+        line: &mut Vec<S::Action>,
+    ) -> f32 {
         stats.iterations += 1;
-        debug_assert!(self.position.halfmoves() <= MAX_HALFMOVES);
+        let out_of_nodes = stats.is_out_of_nodes();
+        let widened = self.is_widened(settings) || out_of_nodes && self.has_children_to_search();
         let normalized_value: f32 = match self.state {
-            NodeState::FullyExpanded => {
+            NodeState::Expandable if !widened && out_of_nodes => self.replay(stats, settings, line),
+            NodeState::Expandable if !widened => {
+                let child = self.expand(settings);
+                self.children.push(child);
+                if self.is_fully_expanded() {
+                    self.state = NodeState::FullyExpanded;
+                }
+                stats.increase_mcts_depth();
+                let child = self.children.last_mut().unwrap();
+                // starts Empty, so now do playout
+                let normalized_value = child.iteration_with_line(rng, stats, settings, line);
+                line.push(child.action.clone().unwrap());
+                stats.decrease_mcts_depth();
+                normalized_value
+            }
+            // a widened node searches the children it has until it's visited enough for another,
+            // as every node does once the tree has run out of nodes
+            NodeState::FullyExpanded | NodeState::Expandable => {
                 let normalized_value = {
-                    let child = most_interesting_child(self, settings);
+                    let child = most_interesting_child(self, settings, rng);
                     stats.increase_mcts_depth();
-                    let normalized_value = child.iteration(rng, stats, settings);
+                    let normalized_value = child.iteration_with_line(rng, stats, settings, line);
+                    line.push(child.action.clone().unwrap());
                     stats.decrease_mcts_depth();
                     normalized_value
                 };
                 normalized_value
             }
-            NodeState::Expandable => {
-                let candidate_actions = self.actions_with_no_children();
-                let mut child = self.expand(&candidate_actions);
-                self.children.push(child);
-                stats.increase_mcts_depth();
-                let normalized_value = self
-                    .children
-                    .last_mut()
-                    .unwrap()
-                    .iteration(rng, stats, settings); // starts Empty, so now do playout
-                stats.decrease_mcts_depth();
-                if candidate_actions.len() == 1 {
-                    self.state = NodeState::FullyExpanded;
-                }
-                normalized_value
-            }
             NodeState::Empty => {
-                if self.is_game_over() {
-                    self.value = self.outcome().unwrap().reward();
-                    self.state = NodeState::LeafNode;
-                    stats.leaf_nodes += 1;
-                } else {
-                    self.value = playout(self.position.clone(), stats, settings);
-                    self.state = NodeState::Expandable;
-                }
-                self.minimax = self.value;
+                self.history_dependent = self.game.depends_on_history();
+                let normalized_value = match self.game.terminal_value() {
+                    Some(value) => {
+                        self.value = value;
+                        self.pessimistic = value;
+                        self.optimistic = value;
+                        self.state = NodeState::LeafNode;
+                        stats.leaf_nodes += 1;
+                        self.minimax = self.value;
+                        self.normalized_value()
+                    }
+                    None => match self.adopt_transposition(stats, settings) {
+                        Some(normalized_value) => normalized_value,
+                        None => {
+                            let (value, playout_line) = self.game.evaluate_line(stats, settings);
+                            self.value = value;
+                            line.extend(playout_line.into_iter().rev());
+                            self.state = NodeState::Expandable;
+                            self.set_bounds_for_game_in_play();
+                            self.minimax = self.value;
+                            self.normalized_value()
+                        }
+                    },
+                };
                 stats.nodes_created += 1;
-                self.normalized_value()
+                normalized_value
             }
             NodeState::LeafNode => {
                 panic!("IMPOSSIBLE LeafNode");
@@ -71,44 +137,231 @@ impl MCTS for TreeNode {
                 panic!("IMPOSSIBLE FullySearched");
             }
         };
+        self.backpropagate(line, normalized_value, settings);
+        normalized_value
+    }
+
+    // what an iteration through this node learnt, once its children have been updated
+    fn backpropagate(&mut self, line: &[S::Action], normalized_value: f32, settings: &Settings) {
+        if settings.rave.is_some() {
+            self.update_amaf(line, normalized_value);
+        }
         self.n += 1;
         self.q += normalized_value;
         self.update_based_on_children();
-        normalized_value
+        self.set_minimax_based_on_widened_children(settings);
+        self.refresh_from_transposition(settings);
+        self.store_transposition(settings);
     }
 
-    fn expand(&self, candidate_actions: &Vec<Move>) -> TreeNode {
-        //TODO is this actually better than random?
-        let action = candidate_actions
-            .iter()
-            .max_by(|a, b| {
-                let position_a = self.position.clone_and_play(a);
-                let position_b = self.position.clone_and_play(b);
-                position_a
-                    .color_relative_reward()
-                    .cmp(&position_b.color_relative_reward())
-            })
+    // all moves as first: each child whose action this node's player made anywhere in the line
+    // learns from the result as if it had been played here
+    fn update_amaf(&mut self, line: &[S::Action], normalized_value: f32) {
+        let ours: Vec<&S::Action> = line.iter().rev().step_by(2).collect();
+        for child in self.children.iter_mut() {
+            if ours.iter().any(|a| child.action.as_ref() == Some(a)) {
+                child.amaf_n += 1;
+                child.amaf_q += normalized_value;
+            }
+        }
+    }
+
+    // a new node takes over what's been learned about its position where else it was reached,
+    // instead of a playout. The mean value of those visits is what's backed up, as a single visit,
+    // since they were made through other parents. A position proven elsewhere has no children
+    // here, so it's a leaf with the bounds it was proven to
+    fn adopt_transposition(&mut self, stats: &mut RunStats, settings: &Settings) -> Option<f32> {
+        if self.game.depends_on_history() {
+            return None;
+        }
+        let table = settings.transpositions.as_ref()?;
+        let key = self.game.transposition_key()?;
+        stats.tt_probes += 1;
+        let entry = table.probe(key)?;
+        stats.tt_hits += 1;
+        self.value = entry.value;
+        self.minimax = entry.minimax;
+        self.pessimistic = entry.pessimistic;
+        self.optimistic = entry.optimistic;
+        self.state = if entry.proven {
+            stats.leaf_nodes += 1;
+            NodeState::LeafNode
+        } else {
+            NodeState::Expandable
+        };
+        Some(entry.q / entry.n as f32)
+    }
+
+    // the position goes on being searched through its other parents, so on every visit the node
+    // takes the mean of the entry if it's searched more there. Its own visits stay its own, so
+    // they still add up to its parent's
+    fn refresh_from_transposition(&mut self, settings: &Settings) {
+        if self.history_dependent {
+            return;
+        }
+        let entry = match (&settings.transpositions, self.game.transposition_key()) {
+            (Some(table), Some(key)) => table.probe(key),
+            _ => None,
+        };
+        if let Some(entry) = entry {
+            if entry.n > self.n {
+                self.q = entry.q / entry.n as f32 * self.n as f32;
+            }
+        }
+    }
+
+    fn store_transposition(&self, settings: &Settings) {
+        if self.history_dependent {
+            return;
+        }
+        if let (Some(table), Some(key)) = (&settings.transpositions, self.game.transposition_key())
+        {
+            table.store(Entry {
+                key,
+                n: self.n,
+                q: self.q,
+                value: self.value,
+                minimax: self.minimax,
+                pessimistic: self.pessimistic,
+                optimistic: self.optimistic,
+                proven: !self.is_searchable(),
+            });
+        }
+    }
+
+    fn expand(&mut self, settings: &Settings) -> TreeNode<S> {
+        let (action, prior) = self
+            .next_unexpanded(settings)
             .expect("no children to expand");
 
-        TreeNode::new_empty_child(action.clone(), self)
+        let mut child = TreeNode::new_empty_child(action, self);
+        child.prior = prior;
+        child
+    }
+
+    // another playout from a node that may not grow, backed up as if it were a new child's
+    fn replay(&self, stats: &mut RunStats, settings: &Settings, line: &mut Vec<S::Action>) -> f32 {
+        let (value, playout_line) = self.game.evaluate_line(stats, settings);
+        line.extend(playout_line.into_iter().rev());
+        normalize(value)
+    }
+
+    // the priors and promise are worked out over every action once, not on each expansion
+    fn next_unexpanded(&mut self, settings: &Settings) -> Option<(S::Action, f32)> {
+        if self.unexpanded.is_none() {
+            let actions = self.actions_with_no_children();
+            let by_promise = self.actions_by_promise(actions, settings);
+            self.set_unexpanded(by_promise);
+        }
+        let unexpanded = self.unexpanded.as_mut().unwrap();
+        let next = unexpanded.pop();
+        if unexpanded.is_empty() {
+            *unexpanded = Vec::new(); // gives back the memory
+        }
+        next
     }
 
-    fn actions_with_no_children(&self) -> Vec<Move> {
-        debug_assert!(self.position.legals().len() > 0);
-        let child_actions: Vec<Move> = self
+    // from actions in order of promise, which may have been worked out while the node was
+    // unlocked, so any that have a child by now are left out
+    fn set_unexpanded(&mut self, by_promise: Vec<(S::Action, f32)>) {
+        let children = &self.children;
+        let mut unexpanded: Vec<(S::Action, f32)> = by_promise
+            .into_iter()
+            .filter(|(action, _)| !children.iter().any(|c| c.action.as_ref() == Some(action)))
+            .collect();
+        unexpanded.reverse();
+        self.unexpanded = Some(unexpanded);
+    }
+
+    fn is_fully_expanded(&self) -> bool {
+        self.unexpanded.as_ref().map_or(false, |u| u.is_empty())
+    }
+
+    // the order children are expanded in, with their priors. Under PUCT the likeliest come first,
+    // so if we run out of time the best moves have been looked at
+    fn actions_by_promise(
+        &self,
+        actions: Vec<S::Action>,
+        settings: &Settings,
+    ) -> Vec<(S::Action, f32)> {
+        let priors = self.action_priors(settings);
+        let coefficient = self.player_to_move().coefficient();
+        // reversed so that ties go to the later action
+        let mut scored: Vec<(S::Action, f32, f32)> = actions
+            .into_iter()
+            .rev()
+            .map(|action| {
+                let prior = prior_of(&priors, &action);
+                let promise = match settings.selection_formula {
+                    //TODO is this actually better than random?
+                    SelectionFormula::Ucb1 => {
+                        let mut game = self.game.clone();
+                        game.apply(&action);
+                        (coefficient * game.heuristic_value()) as f32
+                    }
+                    SelectionFormula::Puct => prior,
+                };
+                (action, prior, promise)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Equal));
+        scored
+            .into_iter()
+            .map(|(action, prior, _)| (action, prior))
+            .collect()
+    }
+
+    // progressive widening: an Expandable node has as many children as its visits allow, and
+    // one of them is still worth searching
+    fn is_widened(&self, settings: &Settings) -> bool {
+        let widening = match settings.widening {
+            Some(widening) => widening,
+            None => return false,
+        };
+        self.state == NodeState::Expandable
+            && self.children.len() >= widening.max_children(self.n)
+            && self.has_children_to_search()
+    }
+
+    fn has_children_to_search(&self) -> bool {
+        let secured = self.secured_by_children();
+        self.children
+            .iter()
+            .any(|c| c.is_searchable() && !c.is_pruned(secured))
+    }
+
+    // shared out over every legal action, so a child's prior doesn't depend on which of its
+    // siblings exist yet. UCB1 has no use for them, so they aren't worked out
+    fn action_priors(&self, settings: &Settings) -> Vec<(S::Action, f32)> {
+        if settings.selection_formula != SelectionFormula::Puct {
+            return vec![];
+        }
+        let actions = self.game.legal_actions();
+        let scores: Vec<f32> = actions.iter().map(|a| self.game.action_score(a)).collect();
+        actions
+            .into_iter()
+            .zip(priors(&scores, settings.prior_temperature))
+            .collect()
+    }
+
+    fn actions_with_no_children(&self) -> Vec<S::Action> {
+        let legal_actions = self.game.legal_actions();
+        debug_assert!(!legal_actions.is_empty());
+        let child_actions: Vec<S::Action> = self
             .children
             .iter()
             .map(|c| c.action.clone().unwrap())
             .collect();
-        self.position
-            .legals()
+        legal_actions
             .into_iter()
             .filter(|a| !child_actions.contains(a))
             .collect()
     }
 
     fn update_root_based_on_children(&mut self) {
-        if self.children.iter().all(|c| c.state != NodeState::Empty) {
+        if self.children.iter().all(|c| c.state != NodeState::Empty)
+            && self.actions_with_no_children().is_empty()
+        {
             // ensures we can now set it to FullySearched if all children are leaves
             // and allow us to set minimax
             self.state = NodeState::FullyExpanded;
@@ -117,38 +370,132 @@ impl MCTS for TreeNode {
     }
 
     fn update_based_on_children(&mut self) {
-        if self.state == NodeState::FullyExpanded {
+        self.history_dependent =
+            self.history_dependent || self.children.iter().any(|c| c.history_dependent);
+        self.set_bounds_based_on_children();
+        // MCTS-Solver: one winning move proves a win, whatever the moves not yet tried do. It's
+        // searched on until no other move could mate sooner
+        if let Some(minimax) = self.winning_child().map(|c| c.minimax) {
+            self.minimax = backed_up(minimax);
+            if self.pessimistic == self.optimistic || self.cannot_mate_sooner() {
+                self.state = NodeState::FullySearched;
+            }
+        } else if self.state == NodeState::FullyExpanded {
             self.set_minimax_based_on_children();
-            if self.children.iter().all(|c| !c.is_searchable()) {
+            // a loss or a draw needs every move proven, or bounds that can't move any more
+            if self.proof().is_some() || self.children.iter().all(|c| !c.is_searchable()) {
                 self.state = NodeState::FullySearched
             }
         }
+        // pruned children keep their estimates, which mustn't outweigh what's proven
+        self.minimax = self.minimax.max(self.pessimistic).min(self.optimistic);
+    }
+
+    // the player to move can't win before their move, nor the other player before the one after
+    fn set_bounds_for_game_in_play(&mut self) {
+        let (first_plies, second_plies) = match self.player_to_move() {
+            Player::First => (1, 2),
+            Player::Second => (2, 1),
+        };
+        self.optimistic = self.optimistic.min(MAX_REWARD - first_plies);
+        self.pessimistic = self.pessimistic.max(MIN_REWARD + second_plies);
+    }
+
+    // every move has been tried, and those still searchable can't beat the mate secured
+    fn cannot_mate_sooner(&self) -> bool {
+        let player = self.player_to_move();
+        let secured = self.secured_by_children();
+        self.state == NodeState::FullyExpanded
+            && self
+                .children
+                .iter()
+                .all(|c| !c.is_searchable() || c.bounds_for(player).1 <= secured)
     }
 
     fn normalized_value(&self) -> f32 {
-        (self.value as f32 / 9590.).min(1.) // (8 * 929) + (2 * 479) + (2 * 320) + (2 * 280)
-                                            // TODO test 8 queen positions and other extremes
+        normalize(self.value)
     }
 
     fn set_minimax_based_on_children(&mut self) {
-        assert!(self.state == NodeState::FullyExpanded);
+        assert!(self.state == NodeState::FullyExpanded || self.state == NodeState::Expandable);
         let new_minimax = self
             .children
             .iter()
+            .filter(|c| c.state != NodeState::Empty)
             .map(|c| c.minimax)
             .max_by(|v1, v2| {
-                let relative_v1 = v1 * self.turn().coefficient();
-                let relative_v2 = v2 * self.turn().coefficient();
+                let relative_v1 = v1 * self.player_to_move().coefficient();
+                let relative_v2 = v2 * self.player_to_move().coefficient();
                 relative_v1.cmp(&relative_v2)
             })
             .expect("no children to choose minimax from");
-        self.minimax = new_minimax;
+        self.minimax = backed_up(new_minimax);
+    }
+
+    // under progressive widening the moves not widened to yet are taken to be no better than the
+    // ones searched, so the minimax comes from the children so far
+    fn set_minimax_based_on_widened_children(&mut self, settings: &Settings) {
+        if settings.widening.is_none()
+            || self.state != NodeState::Expandable
+            || self.children.iter().all(|c| c.state == NodeState::Empty)
+        {
+            return;
+        }
+        self.set_minimax_based_on_children();
+        self.minimax = self.minimax.max(self.pessimistic).min(self.optimistic);
+    }
+
+    fn set_bounds_based_on_children(&mut self) {
+        if self.children.is_empty() {
+            return;
+        }
+        let player = self.player_to_move();
+        let worst = self.secured_by_children();
+        let best = if self.state == NodeState::Expandable {
+            MAX_REWARD // a move we haven't tried yet could do anything
+        } else {
+            self.children
+                .iter()
+                .map(|c| c.bounds_for(player).1)
+                .max()
+                .unwrap()
+        };
+        let (worst, best) = (backed_up(worst), backed_up(best));
+        match player {
+            Player::First => {
+                self.pessimistic = worst;
+                self.optimistic = best;
+            }
+            Player::Second => {
+                self.pessimistic = -best;
+                self.optimistic = -worst;
+            }
+        }
     }
 
-    fn generate_missing_children(&mut self, _stats: &mut RunStats) {
-        for action in self.actions_with_no_children() {
-            let mut child = TreeNode::new_empty_child(action, &self);
-            self.children.push(child);
+    // all of them, or under progressive widening as many as the root's visits allow
+    fn generate_missing_children(&mut self, _stats: &mut RunStats, settings: &Settings) {
+        while !self.is_widened(settings) {
+            match self.next_unexpanded(settings) {
+                Some((action, prior)) => {
+                    let mut child = TreeNode::new_empty_child(action, &self);
+                    child.prior = prior;
+                    self.children.push(child);
+                }
+                None => break,
+            }
         }
     }
 }
+
+fn prior_of<A: PartialEq>(priors: &[(A, f32)], action: &A) -> f32 {
+    priors
+        .iter()
+        .find(|(a, _)| a == action)
+        .map_or(0., |&(_, prior)| prior)
+}
+
+fn normalize(value: Reward) -> f32 {
+    (value as f32 / 9590.).min(1.) // (8 * 929) + (2 * 479) + (2 * 320) + (2 * 280)
+                                   // TODO test 8 queen positions and other extremes
+}
@@ -0,0 +1,183 @@
+use game_state::*;
+use std::cmp::Ordering;
+use std::cmp::Ordering::*;
+use std::f32;
+use tree_node::*;
+
+// how the move to play is picked from the root's children once the search is over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FinalMoveSelection {
+    MaxChild,    // the best mean value, q / n
+    RobustChild, // the most visits
+    MaxRobust,   // the best at both, going by the worse of a child's two ranks
+    SecureChild, // the best lower confidence bound on the minimax
+    Minimax,
+}
+
+pub const FINAL_MOVE_SELECTIONS: [FinalMoveSelection; 5] = [
+    FinalMoveSelection::MaxChild,
+    FinalMoveSelection::RobustChild,
+    FinalMoveSelection::MaxRobust,
+    FinalMoveSelection::SecureChild,
+    FinalMoveSelection::Minimax,
+];
+
+// a move proven best is played, and otherwise the most visited
+pub const DEFAULT_FINAL_MOVE_SELECTION: [FinalMoveSelection; 2] =
+    [FinalMoveSelection::Minimax, FinalMoveSelection::RobustChild];
+
+impl FinalMoveSelection {
+    pub fn name(self) -> &'static str {
+        match self {
+            FinalMoveSelection::MaxChild => "max",
+            FinalMoveSelection::RobustChild => "robust",
+            FinalMoveSelection::MaxRobust => "max-robust",
+            FinalMoveSelection::SecureChild => "secure",
+            FinalMoveSelection::Minimax => "minimax",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FinalMoveSelection> {
+        FINAL_MOVE_SELECTIONS
+            .iter()
+            .cloned()
+            .find(|s| s.name() == name)
+    }
+
+    fn compare<S: GameState>(
+        self,
+        c1: &TreeNode<S>,
+        c2: &TreeNode<S>,
+        siblings: &[TreeNode<S>],
+    ) -> Ordering {
+        match self {
+            FinalMoveSelection::MaxChild => mean(c1).partial_cmp(&mean(c2)).unwrap_or(Equal),
+            FinalMoveSelection::RobustChild => c1.n.cmp(&c2.n),
+            FinalMoveSelection::MaxRobust => {
+                max_robust_rank(c2, siblings).cmp(&max_robust_rank(c1, siblings))
+            }
+            FinalMoveSelection::SecureChild => c1
+                .secure_value()
+                .partial_cmp(&c2.secure_value())
+                .unwrap_or(Equal),
+            FinalMoveSelection::Minimax => c1
+                .best_child_sort_minimax()
+                .cmp(&c2.best_child_sort_minimax()),
+        }
+    }
+}
+
+// a chain of policies, each breaking the ties of the one before, e.g. "minimax,robust"
+pub fn parse_final_move_selection(chain: &str) -> Result<Vec<FinalMoveSelection>, String> {
+    chain
+        .split(',')
+        .map(|name| {
+            FinalMoveSelection::from_name(name.trim())
+                .ok_or(format!("unknown final move selection {}", name.trim()))
+        })
+        .collect()
+}
+
+pub fn final_move_selection_names(chain: &[FinalMoveSelection]) -> String {
+    let names: Vec<&str> = chain.iter().map(|s| s.name()).collect();
+    names.join(",")
+}
+
+pub fn select_final_move<'a, S: GameState>(
+    children: &'a [TreeNode<S>],
+    chain: &[FinalMoveSelection],
+) -> Option<&'a TreeNode<S>> {
+    children.iter().max_by(|c1, c2| {
+        chain
+            .iter()
+            .map(|s| s.compare(c1, c2, children))
+            .find(|o| *o != Equal)
+            .unwrap_or(Equal)
+    })
+}
+
+// for the player who moved to the child
+fn mean<S: GameState>(child: &TreeNode<S>) -> f32 {
+    if child.n == 0 {
+        f32::MIN
+    } else {
+        child.color_relative_q() / child.n as f32
+    }
+}
+
+// how many siblings beat the child at visits or at mean value, whichever is more. 0 is the best
+fn max_robust_rank<S: GameState>(child: &TreeNode<S>, siblings: &[TreeNode<S>]) -> usize {
+    let by_n = siblings.iter().filter(|s| s.n > child.n).count();
+    let by_mean = siblings.iter().filter(|s| mean(s) > mean(child)).count();
+    by_n.max(by_mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_state::*;
+    use game::*;
+
+    // white's first moves, each with its visits, value sum and minimax
+    fn children(stats: &[(u32, f32, Reward)]) -> Vec<TreeNode> {
+        let root: TreeNode = TreeNode::new_root(ChessState::default());
+        root.game
+            .legal_actions()
+            .into_iter()
+            .zip(stats.iter())
+            .map(|(action, &(n, q, minimax))| {
+                let mut child = TreeNode::new_empty_child(action, &root);
+                child.state = NodeState::Expandable;
+                child.n = n;
+                child.q = q;
+                child.minimax = minimax;
+                child
+            })
+            .collect()
+    }
+
+    fn selected(children: &[TreeNode], chain: &str) -> usize {
+        let chain = parse_final_move_selection(chain).unwrap();
+        let best = select_final_move(children, &chain).unwrap();
+        children
+            .iter()
+            .position(|c| c.action == best.action)
+            .unwrap()
+    }
+
+    #[test]
+    fn selects_by_each_policy() {
+        let children = children(&[(100, 10., 30), (40, 8., 50), (90, 18., 20), (2, 1., 90)]);
+        assert_eq!(selected(&children, "max"), 3);
+        assert_eq!(selected(&children, "robust"), 0);
+        assert_eq!(selected(&children, "max-robust"), 2);
+        assert_eq!(selected(&children, "secure"), 1);
+        assert_eq!(selected(&children, "minimax"), 3);
+    }
+
+    #[test]
+    fn breaks_ties_down_the_chain() {
+        let children = children(&[(10, 1., 40), (30, 6., 40), (20, 6., 10)]);
+        assert_eq!(selected(&children, "minimax,robust"), 1);
+        assert_eq!(selected(&children, "robust"), 1);
+        assert_eq!(selected(&children, "minimax,max"), 1);
+        assert_eq!(selected(&children, "max"), 2);
+    }
+
+    #[test]
+    fn parses_chains() {
+        assert_eq!(
+            parse_final_move_selection("minimax, robust"),
+            Ok(vec![
+                FinalMoveSelection::Minimax,
+                FinalMoveSelection::RobustChild
+            ])
+        );
+        assert_eq!(
+            parse_final_move_selection("minimax,best"),
+            Err("unknown final move selection best".to_string())
+        );
+        let chain = parse_final_move_selection("secure,max-robust").unwrap();
+        assert_eq!(final_move_selection_names(&chain), "secure,max-robust");
+    }
+}
@@ -1,3 +1,4 @@
+use error::*;
 use game::*;
 use hash::*;
 use setup::*;
@@ -18,7 +19,7 @@ impl Node {
         }
     }
 
-    pub fn from_fen(fen_str: &str) -> Result<Node, String> {
+    pub fn from_fen(fen_str: &str) -> Result<Node, EngineError> {
         Ok(Node::new(parse_fen_input(fen_str)?))
     }
 
@@ -40,65 +41,8 @@ impl Node {
     }
 
     fn update_hash(&mut self, turn: Color, action: &Move) {
-        let piece = Piece {
-            color: turn,
-            role: action.role(),
-        };
-        match action {
-            Move::Normal {
-                role,
-                from,
-                capture,
-                to,
-                promotion,
-            } => {
-                self.hash.set_piece(*from, piece); // remove from current square
-                if let Some(captured) = capture {
-                    let piece = Piece {
-                        color: !turn,
-                        role: *captured,
-                    }; // TODO, does all this derefing have a performance cost?
-                    self.hash.set_piece(*to, piece); // remove captured piece
-                }
-                if let Some(promoted) = promotion {
-                    let piece = Piece {
-                        color: turn,
-                        role: *promoted,
-                    };
-                    self.hash.set_piece(*to, piece)
-                } else {
-                    self.hash.set_piece(*to, piece); // add at new square
-                }
-            }
-            Move::EnPassant { from, to } => {
-                self.hash.set_piece(*from, piece);
-                self.hash.set_piece(*to, piece);
-                let captured_piece = Piece {
-                    color: !turn,
-                    role: Role::Pawn,
-                };
-                let captured_square = Square::from_coords(to.file(), from.rank());
-                self.hash.set_piece(captured_square, captured_piece);
-            }
-            Move::Castle { king, rook } => {
-                let king_piece = Piece {
-                    color: turn,
-                    role: Role::King,
-                };
-                let rook_piece = Piece {
-                    color: turn,
-                    role: Role::Rook,
-                };
-                self.hash.set_piece(*king, king_piece);
-                self.hash.set_piece(*rook, rook_piece);
-                let castling_side = action.castling_side().unwrap();
-                self.hash.set_piece(castling_side.king_to(turn), king_piece);
-                self.hash.set_piece(castling_side.rook_to(turn), rook_piece);
-            }
-            _ => {}
-        };
-        self.hash.set_ep(self.position.ep_square()); // removes ep if we had it or noops
-        self.hash.flip_color();
+        self.hash
+            .move_pieces(turn, self.position.ep_square(), action);
     }
 
     fn maybe_rehash_castles(&mut self, turn: Color, action: &Move) {
@@ -224,6 +168,15 @@ mod tests {
         assert_hashes_match_for_move("r3k2r/8/8/8/8/8/8/R3K2R b KQkq -", "e8c8");
     }
 
+    #[test]
+    fn updates_hash_for_chess960_castles() {
+        assert_hashes_match_for_move("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb -", "e1b1");
+        assert_hashes_match_for_move("1r2k1r1/8/8/8/8/8/8/1R2K1R1 b GBgb -", "e8b8");
+        // the king doesn't move, or lands on its rook's square
+        assert_hashes_match_for_move("r5kr/8/8/8/8/8/8/R5KR w HAha -", "g1h1");
+        assert_hashes_match_for_move("r5kr/8/8/8/8/8/8/R5KR b HAha -", "g8a8");
+    }
+
     #[test]
     fn incrementally_creates_hash() {
         unsafe { init_hash_keys(Settings::test_default()) };
@@ -0,0 +1,446 @@
+use game::*;
+use move_selection::*;
+use search_strategy::*;
+use settings::*;
+use std::sync::Arc;
+use table::*;
+use transposition::*;
+use uct::*;
+
+// the Settings a GUI or tuning script can change over the protocol
+const EXPLORATION: &str = "Exploration x1000"; // Settings::c is a float, but spins are integers
+const THREADS: &str = "Threads";
+const PARALLELISM: &str = "Parallelism";
+const PLAYOUT_DEPTH: &str = "Playout Depth";
+const QUIESCENCE_SEARCH: &str = "Quiescence Search";
+const SEED: &str = "Seed";
+const TREE_DISPLAY_DEPTH: &str = "Tree Display Depth"; // 0 means unlimited
+const TREE_DISPLAY_LENGTH: &str = "Tree Display Length"; // 0 means unlimited
+const PRINT_TREE: &str = "Print Tree";
+const PGN_FILE: &str = "PGN File"; // empty means don't save games
+const RESIGN_SCORE: &str = "Resign Score"; // 0 means never resign
+const RESIGN_MOVES: &str = "Resign Moves";
+const DRAW_SCORE: &str = "Draw Score"; // 0 means never offer a draw
+const DRAW_MOVES: &str = "Draw Moves";
+pub const CHESS960: &str = "UCI_Chess960"; // xboard selects this with the variant command instead
+const FINAL_MOVE_SELECTION: &str = "Final Move Selection"; // e.g. minimax,robust
+const SELECTION_FORMULA: &str = "Selection Formula";
+const PRIOR_TEMPERATURE: &str = "Prior Temperature";
+const WIDENING: &str = "Widening x1000"; // 0 means every move is expanded before going deeper
+const RAVE: &str = "RAVE Schedule"; // e.g. equivalence=1000, or none
+const TRANSPOSITIONS: &str = "Transpositions MB"; // 0 means nodes don't share statistics
+const PLAYOUT_HASH: &str = "Playout Hash MB"; // 0 means every playout searches from scratch
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionType {
+    Spin { default: i64, min: i64, max: i64 },
+    Check { default: bool },
+    Str { default: String },
+    Combo { default: &'static str, choices: Vec<&'static str> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineOption {
+    pub name: &'static str,
+    pub option_type: OptionType,
+}
+
+impl EngineOption {
+    // the value of xboard's feature option="..."
+    pub fn to_xboard_feature(&self) -> String {
+        match self.option_type {
+            OptionType::Spin { default, min, max } => {
+                format!("{} -spin {} {} {}", self.name, default, min, max)
+            }
+            OptionType::Check { default } => format!("{} -check {}", self.name, default as u8),
+            OptionType::Str { ref default } => format!("{} -string {}", self.name, default),
+            // the default is marked with a *
+            OptionType::Combo {
+                default,
+                ref choices,
+            } => {
+                let choices: Vec<String> = choices
+                    .iter()
+                    .map(|c| if *c == default { format!("*{}", c) } else { c.to_string() })
+                    .collect();
+                format!("{} -combo {}", self.name, choices.join(" /// "))
+            }
+        }
+    }
+
+    pub fn to_uci_option(&self) -> String {
+        match self.option_type {
+            OptionType::Spin { default, min, max } => format!(
+                "option name {} type spin default {} min {} max {}",
+                self.name, default, min, max
+            ),
+            OptionType::Check { default } => {
+                format!("option name {} type check default {}", self.name, default)
+            }
+            OptionType::Str { ref default } => format!(
+                "option name {} type string default {}",
+                self.name,
+                if default.is_empty() { "<empty>" } else { default }
+            ),
+            OptionType::Combo {
+                default,
+                ref choices,
+            } => {
+                let vars: Vec<String> = choices.iter().map(|c| format!(" var {}", c)).collect();
+                format!(
+                    "option name {} type combo default {}{}",
+                    self.name,
+                    default,
+                    vars.concat()
+                )
+            }
+        }
+    }
+
+    // checks use 0/1 in xboard and true/false in UCI, so either is accepted
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        let value = value.trim();
+        match self.option_type {
+            OptionType::Spin { min, max, .. } => value
+                .parse::<i64>()
+                .ok()
+                .filter(|v| *v >= min && *v <= max)
+                .map(OptionValue::Int)
+                .ok_or(format!(
+                    "invalid value {} for option {}: expected an integer from {} to {}",
+                    value, self.name, min, max
+                )),
+            OptionType::Check { .. } => match value {
+                "true" | "1" => Ok(OptionValue::Bool(true)),
+                "false" | "0" => Ok(OptionValue::Bool(false)),
+                _ => Err(format!(
+                    "invalid value {} for option {}: expected true or false",
+                    value, self.name
+                )),
+            },
+            OptionType::Str { .. } => match value {
+                "<empty>" => Ok(OptionValue::Str(String::new())),
+                _ => Ok(OptionValue::Str(value.to_string())),
+            },
+            OptionType::Combo { ref choices, .. } => choices
+                .iter()
+                .find(|c| **c == value)
+                .map(|c| OptionValue::Str(c.to_string()))
+                .ok_or(format!(
+                    "invalid value {} for option {}: expected one of {}",
+                    value,
+                    self.name,
+                    choices.join(", ")
+                )),
+        }
+    }
+}
+
+// defaults come from the current settings, which may have been set by env vars
+pub fn engine_options(settings: &Settings) -> Vec<EngineOption> {
+    let spin = |name, default: i64, min, max| EngineOption {
+        name,
+        option_type: OptionType::Spin { default, min, max },
+    };
+    let check = |name, default| EngineOption {
+        name,
+        option_type: OptionType::Check { default },
+    };
+    vec![
+        spin(EXPLORATION, (settings.c * 1000.).round() as i64, 0, 10000),
+        spin(THREADS, settings.max_threads as i64, 1, 255),
+        spin(PLAYOUT_DEPTH, settings.playout_depth as i64, 0, 100),
+        check(QUIESCENCE_SEARCH, settings.q_search),
+        spin(SEED, settings.starting_seed as i64, 0, 255),
+        spin(
+            TREE_DISPLAY_DEPTH,
+            settings.max_tree_display_depth.unwrap_or(0) as i64,
+            0,
+            255,
+        ),
+        spin(
+            TREE_DISPLAY_LENGTH,
+            settings.max_tree_display_length.unwrap_or(0) as i64,
+            0,
+            255,
+        ),
+        check(PRINT_TREE, settings.print_tree),
+        EngineOption {
+            name: PGN_FILE,
+            option_type: OptionType::Str {
+                default: settings.pgn_file.clone().unwrap_or_default(),
+            },
+        },
+        spin(
+            RESIGN_SCORE,
+            settings.resign_score.unwrap_or(0) as i64,
+            -10000,
+            0,
+        ),
+        spin(RESIGN_MOVES, settings.resign_moves as i64, 1, 100),
+        spin(DRAW_SCORE, settings.draw_score.unwrap_or(0) as i64, 0, 1000),
+        spin(DRAW_MOVES, settings.draw_moves as i64, 1, 100),
+        check(CHESS960, settings.chess960),
+        EngineOption {
+            name: FINAL_MOVE_SELECTION,
+            option_type: OptionType::Str {
+                default: final_move_selection_names(&settings.final_move_selection),
+            },
+        },
+        EngineOption {
+            name: SELECTION_FORMULA,
+            option_type: OptionType::Combo {
+                default: settings.selection_formula.name(),
+                choices: SELECTION_FORMULAS.iter().map(|f| f.name()).collect(),
+            },
+        },
+        spin(
+            PRIOR_TEMPERATURE,
+            settings.prior_temperature.round() as i64,
+            1,
+            10000,
+        ),
+        spin(
+            WIDENING,
+            settings.widening.map_or(0, |w| (w.c * 1000.).round() as i64),
+            0,
+            100000,
+        ),
+        EngineOption {
+            name: RAVE,
+            option_type: OptionType::Str {
+                default: rave_schedule_name(settings.rave),
+            },
+        },
+        spin(
+            TRANSPOSITIONS,
+            settings.transpositions.as_ref().map_or(0, |t| t.mb() as i64),
+            0,
+            4096,
+        ),
+        spin(
+            PLAYOUT_HASH,
+            settings
+                .playout_table
+                .as_ref()
+                .map_or(0, |t| t.size_mb() as i64),
+            0,
+            4096,
+        ),
+        EngineOption {
+            name: PARALLELISM,
+            option_type: OptionType::Combo {
+                default: settings.parallelism.name(),
+                choices: PARALLELISMS.iter().map(|p| p.name()).collect(),
+            },
+        },
+    ]
+}
+
+// option names are matched case insensitively, as UCI requires
+pub fn set_option(settings: &mut Settings, name: &str, value: &str) -> Result<(), String> {
+    let option = engine_options(settings)
+        .into_iter()
+        .find(|o| o.name.eq_ignore_ascii_case(name.trim()))
+        .ok_or(format!("unknown option {}", name))?;
+    let limit = |v: i64| if v == 0 { None } else { Some(v as u8) };
+    let threshold = |v: i64| if v == 0 { None } else { Some(v as Reward) };
+    let widening = settings.widening.unwrap_or(DEFAULT_WIDENING);
+    match (option.name, option.parse(value)?) {
+        (EXPLORATION, OptionValue::Int(v)) => settings.c = v as f32 / 1000.,
+        (THREADS, OptionValue::Int(v)) => settings.max_threads = v as u16,
+        (PLAYOUT_DEPTH, OptionValue::Int(v)) => settings.playout_depth = v as isize,
+        (QUIESCENCE_SEARCH, OptionValue::Bool(b)) => settings.q_search = b,
+        (SEED, OptionValue::Int(v)) => settings.starting_seed = v as u8,
+        (TREE_DISPLAY_DEPTH, OptionValue::Int(v)) => settings.max_tree_display_depth = limit(v),
+        (TREE_DISPLAY_LENGTH, OptionValue::Int(v)) => settings.max_tree_display_length = limit(v),
+        (PRINT_TREE, OptionValue::Bool(b)) => settings.print_tree = b,
+        (PGN_FILE, OptionValue::Str(s)) => {
+            settings.pgn_file = if s.is_empty() { None } else { Some(s) }
+        }
+        (RESIGN_SCORE, OptionValue::Int(v)) => settings.resign_score = threshold(v),
+        (RESIGN_MOVES, OptionValue::Int(v)) => settings.resign_moves = v as u32,
+        (DRAW_SCORE, OptionValue::Int(v)) => settings.draw_score = threshold(v),
+        (DRAW_MOVES, OptionValue::Int(v)) => settings.draw_moves = v as u32,
+        (CHESS960, OptionValue::Bool(b)) => settings.chess960 = b,
+        (FINAL_MOVE_SELECTION, OptionValue::Str(s)) => {
+            settings.final_move_selection = parse_final_move_selection(&s)?
+        }
+        (SELECTION_FORMULA, OptionValue::Str(s)) => {
+            settings.selection_formula = parse_selection_formula(&s)?
+        }
+        (PRIOR_TEMPERATURE, OptionValue::Int(v)) => settings.prior_temperature = v as f32,
+        (WIDENING, OptionValue::Int(v)) => {
+            settings.widening = if v == 0 {
+                None
+            } else {
+                Some(Widening {
+                    c: v as f32 / 1000.,
+                    ..widening
+                })
+            }
+        }
+        (RAVE, OptionValue::Str(s)) => settings.rave = parse_rave_schedule(&s)?,
+        (TRANSPOSITIONS, OptionValue::Int(v)) => {
+            settings.transpositions = if v == 0 {
+                None
+            } else {
+                Some(Arc::new(TranspositionTable::with_mb(v as u64)))
+            }
+        }
+        (PLAYOUT_HASH, OptionValue::Int(v)) => {
+            settings.playout_table = if v == 0 {
+                None
+            } else {
+                Some(Arc::new(Table::empty_mb(v as usize)))
+            }
+        }
+        (PARALLELISM, OptionValue::Str(s)) => settings.parallelism = parse_parallelism(&s)?,
+        (name, value) => return Err(format!("cannot set option {} to {:?}", name, value)),
+    }
+    info!("set option {} to {}", option.name, value.trim());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advertises_current_settings() {
+        let settings = Settings {
+            c: 0.175,
+            max_threads: 4,
+            ..Default::default()
+        };
+        let options = engine_options(&settings);
+        assert_eq!(
+            options[0].to_xboard_feature(),
+            "Exploration x1000 -spin 175 0 10000"
+        );
+        assert_eq!(
+            options[1].to_uci_option(),
+            "option name Threads type spin default 4 min 1 max 255"
+        );
+        let q_search = options.iter().find(|o| o.name == QUIESCENCE_SEARCH).unwrap();
+        assert_eq!(q_search.to_xboard_feature(), "Quiescence Search -check 1");
+        assert_eq!(
+            q_search.to_uci_option(),
+            "option name Quiescence Search type check default true"
+        );
+        let formula = options.iter().find(|o| o.name == SELECTION_FORMULA).unwrap();
+        assert_eq!(
+            formula.to_xboard_feature(),
+            "Selection Formula -combo *ucb1 /// puct"
+        );
+        assert_eq!(
+            formula.to_uci_option(),
+            "option name Selection Formula type combo default ucb1 var ucb1 var puct"
+        );
+    }
+
+    #[test]
+    fn sets_options() {
+        let mut settings: Settings = Default::default();
+        set_option(&mut settings, "Exploration x1000", "250").unwrap();
+        set_option(&mut settings, "threads", "2").unwrap();
+        set_option(&mut settings, "Quiescence Search", "0").unwrap();
+        set_option(&mut settings, "Print Tree", "true").unwrap();
+        set_option(&mut settings, "Tree Display Depth", "0").unwrap();
+        set_option(&mut settings, "Tree Display Length", "12").unwrap();
+        set_option(&mut settings, "PGN File", "games.pgn").unwrap();
+        set_option(&mut settings, "UCI_Chess960", "true").unwrap();
+        set_option(&mut settings, "Final Move Selection", "max-robust,minimax").unwrap();
+        set_option(&mut settings, "Selection Formula", "puct").unwrap();
+        set_option(&mut settings, "Prior Temperature", "350").unwrap();
+        set_option(&mut settings, "Widening x1000", "1500").unwrap();
+        set_option(&mut settings, "Resign Score", "-2000").unwrap();
+        set_option(&mut settings, "Draw Score", "10").unwrap();
+        assert_eq!(settings.c, 0.25);
+        assert_eq!(settings.max_threads, 2);
+        assert!(!settings.q_search);
+        assert!(settings.print_tree);
+        assert_eq!(settings.max_tree_display_depth, None);
+        assert_eq!(settings.max_tree_display_length, Some(12));
+        assert_eq!(settings.pgn_file, Some("games.pgn".to_string()));
+        assert!(settings.chess960);
+        assert_eq!(
+            settings.final_move_selection,
+            vec![FinalMoveSelection::MaxRobust, FinalMoveSelection::Minimax]
+        );
+        assert_eq!(settings.selection_formula, SelectionFormula::Puct);
+        assert_eq!(settings.prior_temperature, 350.);
+        assert_eq!(
+            settings.widening,
+            Some(Widening {
+                c: 1.5,
+                exponent: 0.5
+            })
+        );
+        assert_eq!(settings.resign_score, Some(-2000));
+        assert_eq!(settings.draw_score, Some(10));
+        set_option(&mut settings, "Widening x1000", "0").unwrap();
+        assert_eq!(settings.widening, None);
+        set_option(&mut settings, "RAVE Schedule", "mse=0.1").unwrap();
+        assert_eq!(settings.rave, Some(RaveSchedule::MinimumMse(0.1)));
+        set_option(&mut settings, "RAVE Schedule", "equivalence=1000").unwrap();
+        assert_eq!(settings.rave, Some(RaveSchedule::Equivalence(1000.)));
+        assert_eq!(rave_schedule_name(settings.rave), "equivalence=1000");
+        set_option(&mut settings, "RAVE Schedule", "none").unwrap();
+        assert_eq!(settings.rave, None);
+        set_option(&mut settings, "Transpositions MB", "16").unwrap();
+        assert_eq!(settings.transpositions.as_ref().map(|t| t.mb()), Some(16));
+        set_option(&mut settings, "Transpositions MB", "0").unwrap();
+        assert!(settings.transpositions.is_none());
+        set_option(&mut settings, "Playout Hash MB", "8").unwrap();
+        assert_eq!(settings.playout_table.as_ref().map(|t| t.size_mb()), Some(8));
+        set_option(&mut settings, "Parallelism", "root").unwrap();
+        assert_eq!(settings.parallelism, Parallelism::Root);
+        set_option(&mut settings, "PGN File", "<empty>").unwrap();
+        assert_eq!(settings.pgn_file, None);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut settings: Settings = Default::default();
+        assert_eq!(
+            set_option(&mut settings, "Threads", "0"),
+            Err("invalid value 0 for option Threads: expected an integer from 1 to 255".to_string())
+        );
+        assert!(set_option(&mut settings, "Seed", "-1").is_err());
+        assert!(set_option(&mut settings, "Playout Depth", "deep").is_err());
+        assert!(set_option(&mut settings, "Print Tree", "yes").is_err());
+        assert_eq!(
+            set_option(&mut settings, "Final Move Selection", "most"),
+            Err("unknown final move selection most".to_string())
+        );
+        assert_eq!(
+            set_option(&mut settings, "Selection Formula", "ucb2"),
+            Err(
+                "invalid value ucb2 for option Selection Formula: expected one of ucb1, puct"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            set_option(&mut settings, "RAVE Schedule", "equivalence"),
+            Err("unknown RAVE schedule equivalence".to_string())
+        );
+        assert!(set_option(&mut settings, "RAVE Schedule", "mse=-1").is_err());
+        assert_eq!(
+            set_option(&mut settings, "Parallelism", "leaf"),
+            Err("invalid value leaf for option Parallelism: expected one of tree, root".to_string())
+        );
+        assert_eq!(
+            set_option(&mut settings, "Hash", "64"),
+            Err("unknown option Hash".to_string())
+        );
+        assert_eq!(settings.max_threads, 4);
+    }
+}
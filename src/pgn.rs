@@ -1,46 +1,451 @@
-use game::Game;
+use game::*;
+use shakmaty::fen;
 use shakmaty::san::SanPlus;
 use shakmaty::*;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::mem;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time_control::*;
+use variant::*;
+
+// PGN export format keeps lines under 80 characters
+const MAX_LINE_LENGTH: usize = 79;
+
+// what the GUI tells us about the game, beyond the moves
+#[derive(Debug, Default, Clone)]
+pub struct GameInfo {
+    pub our_color: Option<Color>,
+    pub opponent_name: Option<String>,
+    pub our_rating: Option<u32>,
+    pub opponent_rating: Option<u32>,
+    pub opponent_is_computer: bool,
+    pub result: Option<String>,
+    pub result_comment: Option<String>, // e.g. {White mates}
+}
+
+impl GameInfo {
+    // parses the arguments of the xboard result command: RESULT {COMMENT}
+    pub fn set_result(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.splitn(2, ' ');
+        let result = parts.next().unwrap_or("");
+        if !["1-0", "0-1", "1/2-1/2", "*"].contains(&result) {
+            return Err(format!("invalid result {}", result));
+        }
+        self.result = Some(result.to_string());
+        self.result_comment = parts
+            .next()
+            .map(|c| c.trim().trim_start_matches('{').trim_end_matches('}').trim())
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_string());
+        Ok(())
+    }
+
+    // xboard sends our rating first
+    pub fn set_ratings(&mut self, args: &str) -> Result<(), String> {
+        let ratings: Vec<&str> = args.split_whitespace().collect();
+        if ratings.len() != 2 {
+            return Err(format!("expected two ratings, got {}", args));
+        }
+        let parse = |r: &str| r.parse::<u32>().map_err(|_| format!("invalid rating {}", r));
+        self.our_rating = Some(parse(ratings[0])?);
+        self.opponent_rating = Some(parse(ratings[1])?);
+        Ok(())
+    }
+}
+
+pub fn to_pgn<P: Game>(start_position: &P, moves: &[Move]) -> String {
+    let outcome = final_position(start_position, moves).outcome();
+    let movetext = wrap(move_tokens(start_position, moves));
+    format!("{}{}", to_result_str(outcome), movetext)
+}
+
+struct Player {
+    name: String,
+    rating: Option<u32>,
+    player_type: &'static str,
+}
+
+// a complete game with the Seven Tag Roster, ready to be appended to an archive
+pub fn to_full_pgn(
+    engine_name: &str,
+    game_info: &GameInfo,
+    time_control: Option<&TimeControl>,
+    date: &str,
+    start_position: &VariantPosition,
+    chess960: bool,
+    moves: &[Move],
+) -> String {
+    let outcome = final_position(start_position, moves).outcome();
+    let result = game_info
+        .result
+        .clone()
+        .unwrap_or_else(|| outcome_str(outcome).to_string());
+    let ours = Player {
+        name: engine_name.to_string(),
+        rating: game_info.our_rating,
+        player_type: "program",
+    };
+    let opponent = Player {
+        name: game_info
+            .opponent_name
+            .clone()
+            .unwrap_or_else(|| "?".to_string()),
+        rating: game_info.opponent_rating,
+        player_type: if game_info.opponent_is_computer {
+            "program"
+        } else {
+            "human"
+        },
+    };
+    let (white, black) = match game_info.our_color {
+        Some(Color::White) => (Some(ours), Some(opponent)),
+        Some(Color::Black) => (Some(opponent), Some(ours)),
+        None => (None, None),
+    };
+
+    let name = |player: &Option<Player>| {
+        player
+            .as_ref()
+            .map_or("?".to_string(), |p| p.name.clone())
+    };
+    let mut tags: Vec<(&str, String)> = vec![
+        ("Event", "?".to_string()),
+        ("Site", "?".to_string()),
+        ("Date", date.to_string()),
+        ("Round", "?".to_string()),
+        ("White", name(&white)),
+        ("Black", name(&black)),
+        ("Result", result.clone()),
+    ];
+    for (tag, player) in [("WhiteElo", &white), ("BlackElo", &black)].iter() {
+        if let Some(rating) = player.as_ref().and_then(|p| p.rating).filter(|r| *r > 0) {
+            tags.push((tag, rating.to_string()));
+        }
+    }
+    for (tag, player) in [("WhiteType", &white), ("BlackType", &black)].iter() {
+        if let Some(ref p) = player {
+            tags.push((tag, p.player_type.to_string()));
+        }
+    }
+    tags.push((
+        "TimeControl",
+        time_control.map_or("-".to_string(), time_control_tag),
+    ));
+    tags.push(("Termination", termination(game_info, outcome).to_string()));
+    // so other tools don't replay the game as standard chess. A Chess960 game's start position
+    // is part of the game, even if it's the standard one
+    let variant = start_position.variant();
+    if chess960 && variant == Variant::Standard {
+        tags.push(("Variant", "Chess960".to_string()));
+    } else if variant != Variant::Standard {
+        tags.push(("Variant", variant.pgn_name().to_string()));
+    }
+    let start_fen = fen::fen(start_position);
+    if chess960 || start_fen != STARTING_POSITION {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", start_fen));
+    }
 
-pub fn to_pgn(start_position: &Chess, moves: &Vec<Move>) -> String {
     let mut pgn_string = String::new();
-    let outcome = push_game_str(start_position.clone(), &moves, &mut pgn_string);
-    format!("{}{}", to_result_str(outcome), pgn_string)
+    for (name, value) in tags {
+        pgn_string.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "'")));
+    }
+    let mut tokens = move_tokens(start_position, moves);
+    if let Some(ref comment) = game_info.result_comment {
+        tokens.push(format!("{{{}}}", comment));
+    }
+    tokens.push(result);
+    pgn_string.push('\n');
+    pgn_string.push_str(&wrap(tokens));
+    pgn_string.push_str("\n\n");
+    pgn_string
 }
 
-fn push_game_str(
-    mut position: Chess,
-    moves: &Vec<Move>,
-    pgn_string: &mut String,
-) -> Option<Outcome> {
-    let mut move_num: u32 = 0;
+pub fn append_pgn(path: &str, pgn: &str) -> Result<(), String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(pgn.as_bytes()))
+        .map_err(|e| format!("could not write pgn to {}: {}", path, e))
+}
+
+// PGN dates are YYYY.MM.DD, in UTC here
+pub fn pgn_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_secs() as i64
+        / 86400;
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn final_position<P: Game>(start_position: &P, moves: &[Move]) -> P {
+    let mut position = start_position.clone();
     for m in moves {
-        let san = SanPlus::from_move(position.clone(), &m).to_string();
         position.play_safe(m);
+    }
+    position
+}
+
+// move numbers follow the start position, so games from setboard start at the right number
+fn move_tokens<P: Game>(start_position: &P, moves: &[Move]) -> Vec<String> {
+    let mut position = start_position.clone();
+    let mut tokens = vec![];
+    for (i, m) in moves.iter().enumerate() {
+        let san = SanPlus::from_move(position.clone(), m).to_string();
         match position.turn() {
-            Color::Black => {
-                move_num += 1;
-                pgn_string.push_str(&format!("{}. {}", move_num, &san));
-            }
-            Color::White => {
-                pgn_string.push_str(&format!(" {} ", &san));
-            }
+            Color::White => tokens.push(format!("{}. {}", position.fullmoves(), san)),
+            Color::Black if i == 0 => tokens.push(format!("{}... {}", position.fullmoves(), san)),
+            Color::Black => tokens.push(san),
         }
+        position.play_safe(m);
     }
-    position.outcome()
+    tokens
 }
 
-fn to_result_str(outcome: Option<Outcome>) -> &'static str {
+fn wrap(tokens: Vec<String>) -> String {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+            lines.push(mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn time_control_tag(time_control: &TimeControl) -> String {
+    let seconds = |d: Duration| format!("{}", d.as_millis() as f64 / 1000.);
+    match *time_control {
+        TimeControl::Conventional {
+            moves_per_session,
+            base,
+            increment,
+        } if increment.as_millis() == 0 => format!("{}/{}", moves_per_session, seconds(base)),
+        TimeControl::Conventional {
+            moves_per_session,
+            base,
+            increment,
+        } => format!(
+            "{}/{}+{}",
+            moves_per_session,
+            seconds(base),
+            seconds(increment)
+        ),
+        TimeControl::Incremental { base, increment } => {
+            format!("{}+{}", seconds(base), seconds(increment))
+        }
+        // every move is its own session
+        TimeControl::FixedTime(time) => format!("1/{}", seconds(time)),
+    }
+}
+
+// the GUI's comment tells us how the game ended when it wasn't over the board
+fn termination(game_info: &GameInfo, outcome: Option<Outcome>) -> &'static str {
+    let comment = game_info
+        .result_comment
+        .clone()
+        .unwrap_or_default()
+        .to_lowercase();
+    if game_info.result.as_ref().is_some_and(|r| r == "*") {
+        "unterminated"
+    } else if comment.contains("time") || comment.contains("flag") {
+        "time forfeit"
+    } else if comment.contains("adjudicat") {
+        "adjudication"
+    } else if comment.contains("disconnect") || comment.contains("abandon") {
+        "abandoned"
+    } else if comment.contains("illegal") {
+        "rules infraction"
+    } else if game_info.result.is_none() && outcome.is_none() {
+        "unterminated"
+    } else {
+        "normal"
+    }
+}
+
+fn outcome_str(outcome: Option<Outcome>) -> &'static str {
     match outcome {
-        Some(o) => match o {
-            Outcome::Draw => "[Result \"1/2-1/2\"]\n",
-            Outcome::Decisive {
-                winner: Color::White,
-            } => "[Result \"1-0\"]\n",
-            Outcome::Decisive {
-                winner: Color::Black,
-            } => "[Result \"0-1\"]\n",
-        },
-        None => "",
+        Some(Outcome::Draw) => "1/2-1/2",
+        Some(Outcome::Decisive {
+            winner: Color::White,
+        }) => "1-0",
+        Some(Outcome::Decisive {
+            winner: Color::Black,
+        }) => "0-1",
+        None => "*",
+    }
+}
+
+fn to_result_str(outcome: Option<Outcome>) -> String {
+    match outcome {
+        Some(_) => format!("[Result \"{}\"]\n", outcome_str(outcome)),
+        None => "".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use setup::*;
+
+    fn moves<P: Game>(start_position: &P, ucis: &[&str]) -> Vec<Move> {
+        let mut position = start_position.clone();
+        ucis.iter()
+            .map(|u| {
+                let m = parse_uci(u, &position);
+                position.play_safe(&m);
+                m
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_seven_tag_roster() {
+        let start_position = VariantPosition::from(parse_fen(STARTING_POSITION));
+        let moves = moves(&start_position, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let mut game_info = GameInfo {
+            our_color: Some(Color::Black),
+            opponent_name: Some("Fool".to_string()),
+            opponent_is_computer: true,
+            ..Default::default()
+        };
+        game_info.set_ratings("2100 1500").unwrap();
+        game_info.set_result("0-1 {Black mates}").unwrap();
+        let time_control = TimeControl::parse_level("40 5 0").unwrap();
+        let pgn = to_full_pgn(
+            "deadbeef",
+            &game_info,
+            Some(&time_control),
+            "2018.10.01",
+            &start_position,
+            false,
+            &moves,
+        );
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"2018.10.01\"]\n\
+             [Round \"?\"]\n\
+             [White \"Fool\"]\n\
+             [Black \"deadbeef\"]\n\
+             [Result \"0-1\"]\n\
+             [WhiteElo \"1500\"]\n\
+             [BlackElo \"2100\"]\n\
+             [WhiteType \"program\"]\n\
+             [BlackType \"program\"]\n\
+             [TimeControl \"40/300\"]\n\
+             [Termination \"normal\"]\n\
+             \n\
+             1. f3 e5 2. g4 Qh4# {Black mates} 0-1\n\n"
+        );
+    }
+
+    #[test]
+    fn records_setup_position() {
+        let fen = "k7/8/1K6/8/8/8/8/7R b - - 10 40";
+        let start_position = VariantPosition::from(parse_fen(fen));
+        let moves = moves(&start_position, &["a8b8", "h1h8"]);
+        let game_info = GameInfo {
+            our_color: Some(Color::White),
+            ..Default::default()
+        };
+        let pgn = to_full_pgn(
+            "deadbeef",
+            &game_info,
+            None,
+            "?",
+            &start_position,
+            false,
+            &moves,
+        );
+        assert!(pgn.contains("[Result \"1-0\"]\n"));
+        assert!(pgn.contains("[TimeControl \"-\"]\n"));
+        assert!(pgn.contains("[SetUp \"1\"]\n"));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]\n", fen)));
+        assert!(pgn.ends_with("\n40... Kb8 41. Rh8# 1-0\n\n"));
+    }
+
+    #[test]
+    fn records_chess960_variant() {
+        let start_position = VariantPosition::from(parse_fen(STARTING_POSITION));
+        let moves = moves(&start_position, &["e2e4"]);
+        let game_info: GameInfo = Default::default();
+        let pgn = to_full_pgn(
+            "deadbeef",
+            &game_info,
+            None,
+            "?",
+            &start_position,
+            true,
+            &moves,
+        );
+        assert!(pgn.contains("[Variant \"Chess960\"]\n"));
+        assert!(pgn.contains("[SetUp \"1\"]\n"));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]\n", STARTING_POSITION)));
+        let pgn = to_full_pgn(
+            "deadbeef",
+            &game_info,
+            None,
+            "?",
+            &start_position,
+            false,
+            &moves,
+        );
+        assert!(!pgn.contains("[Variant "));
+        assert!(!pgn.contains("[FEN "));
+    }
+
+    #[test]
+    fn detects_time_forfeit() {
+        let mut game_info: GameInfo = Default::default();
+        game_info.set_result("1-0 {Black forfeits on time}").unwrap();
+        assert_eq!(termination(&game_info, None), "time forfeit");
+        game_info.set_result("*").unwrap();
+        assert_eq!(termination(&game_info, None), "unterminated");
+        assert!(game_info.set_result("2-0").is_err());
+    }
+
+    #[test]
+    fn wraps_long_games() {
+        let start_position = parse_fen(STARTING_POSITION);
+        let ucis = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let shuffles: Vec<&str> = ucis.iter().cycle().take(40).cloned().collect();
+        let moves = moves(&start_position, &shuffles);
+        let movetext = wrap(move_tokens(&start_position, &moves));
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|l| l.len() <= MAX_LINE_LENGTH));
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(pgn_date(UNIX_EPOCH), "1970.01.01");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(pgn_date(leap_day), "2000.02.29");
     }
 }
@@ -1,66 +1,134 @@
 use eval::*;
 use game::*;
+use hash::*;
 use q_search::*;
 use settings::Settings;
-use shakmaty::{Chess, Position, Setup};
+use shakmaty::*;
 use stats::RunStats;
 use std::cmp::max;
+use table::*;
 
-pub fn playout(starting_position: Chess, stats: &mut RunStats, settings: &Settings) -> Reward {
-    fn negamax(
-        position: Chess,
+pub fn playout<P: Game>(starting_position: P, stats: &mut RunStats, settings: &Settings) -> Reward {
+    playout_line(starting_position, stats, settings).0
+}
+
+// the playout's value along with its principal variation, the moves it expects to be played
+pub fn playout_line<P: Game>(
+    starting_position: P,
+    stats: &mut RunStats,
+    settings: &Settings,
+) -> (Reward, Vec<Move>) {
+    // the hash is kept up to date move by move, and is None without a table
+    fn negamax<P: Game>(
+        position: P,
+        hash: Option<Hash>,
         depth: isize,
         mut alpha: Reward,
         beta: Reward,
         coefficient: Reward,
         stats: &mut RunStats,
         settings: &Settings,
-    ) -> Reward {
+    ) -> (Reward, Vec<Move>) {
         stats.record_playout_depth((settings.playout_depth - depth) as usize);
         if position.is_game_over() {
-            return coefficient * position.outcome().unwrap().reward();
+            return (coefficient * position.outcome().unwrap().reward(), vec![]);
         };
         if depth == 0 {
             stats.playout_leaves += 1;
-            return q_search(position, 0, alpha, beta, coefficient, stats, settings);
+            let value = q_search(position, hash, 0, alpha, beta, coefficient, stats, settings);
+            return (value, vec![]);
+        }
+
+        let table = playout_table(settings, &position);
+        let mut moves = position.legals();
+        if let (Some(table), Some(hash)) = (table, hash) {
+            match table.probe(hash, depth as u8, alpha, beta) {
+                // the rest of the line is left in the table, for Table::pv to follow if wanted
+                (Some(value), best_move) => {
+                    let line = best_move
+                        .filter(|m| moves.contains(m))
+                        .into_iter()
+                        .collect();
+                    return (value, line);
+                }
+                (None, Some(best_move)) => try_first(&mut moves, &best_move),
+                (None, None) => {}
+            }
         }
 
         // TODO try the chess crate here
+        let alpha_before = alpha;
         let mut value = MIN_REWARD;
-        for child_move in position.legals() {
+        let mut line = vec![];
+        for child_move in moves {
             let mut child_position = position.clone(); //TODO can we apply and undo?
             child_position.play_unchecked(&child_move);
-            value = max(
-                -negamax(
-                    child_position,
-                    depth - 1,
-                    -beta,
-                    -alpha,
-                    -coefficient,
-                    stats,
-                    settings,
-                ),
-                value,
+            let child_hash = hash.map(|mut hash| {
+                hash.play(&position, &child_move, &child_position);
+                hash
+            });
+            let (child_value, mut child_line) = negamax(
+                child_position,
+                child_hash,
+                depth - 1,
+                pushed_down(-beta),
+                pushed_down(-alpha),
+                -coefficient,
+                stats,
+                settings,
             );
+            let child_value = -backed_up(child_value);
+            if child_value > value {
+                value = child_value;
+                line.clear();
+                line.push(child_move);
+                line.append(&mut child_line);
+            }
             // print_value(child_move, value, depth);
             alpha = max(alpha, value);
             if alpha >= beta {
                 break; // the possibilites from the position are better than from other siblings, so our opponnent won't give us this position. We can stop evaluatin
             }
         }
-        value
+        if let (Some(table), Some(hash)) = (table, hash) {
+            let bound = bound(value, alpha_before, beta);
+            table.record(hash, value, line.first().cloned(), depth as u8, bound);
+        }
+        (value, line)
     }
 
     let starting_coefficient = starting_position.turn().coefficient();
-    negamax(
+    let starting_hash =
+        playout_table(settings, &starting_position).map(|_| Hash::generate(&starting_position));
+    let (value, line) = negamax(
         starting_position,
+        starting_hash,
         settings.playout_depth,
         MIN_REWARD,
         MAX_REWARD,
         starting_coefficient,
         stats,
         settings,
-    ) * starting_coefficient
+    );
+    (value * starting_coefficient, line)
+}
+
+// what a fail-soft search's value says about the position's real value
+pub fn bound(value: Reward, alpha: Reward, beta: Reward) -> Bound {
+    if value <= alpha {
+        Bound::Upper
+    } else if value >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    }
+}
+
+// moves the table's best move to the front, keeping the others in order
+pub fn try_first(moves: &mut MoveList, best_move: &Move) {
+    if let Some(i) = moves.iter().position(|m| m == best_move) {
+        moves[..=i].rotate_right(1);
+    }
 }
 
 // fn print_value(child_move: Move, value: Reward, depth: isize) {
@@ -0,0 +1,222 @@
+use eval::*;
+use game::*;
+use shakmaty::*;
+use std::f32;
+
+// bonuses for the player making the move, in centipawns
+const CHECK_BONUS: f32 = 150.;
+const LOSING_EXCHANGE_PENALTY: f32 = 200.;
+const SEE_KING_VALUE: i16 = 10000; // so the king is always the last piece to recapture
+
+// how promising a chess move looks before it's searched: the victim of a capture less a tenth of
+// the attacker (MVV-LVA), promotions, checks, a penalty if the exchange on the target square
+// loses material, and the move's piece-square table gain
+pub fn move_score<P: Game>(position: &P, m: &Move) -> f32 {
+    let mut score = 0.;
+    if let Some(victim) = m.capture() {
+        score += (victim.reward() - m.role().reward() / 10) as f32;
+    }
+    if let Some(role) = m.promotion() {
+        score += (role.reward() - Role::Pawn.reward()) as f32;
+    }
+    if position.clone_and_play(m).is_check() {
+        score += CHECK_BONUS;
+    }
+    if static_exchange(position.board(), m) < 0 {
+        score -= LOSING_EXCHANGE_PENALTY;
+    }
+    score + positional_gain(position.board(), m) as f32
+}
+
+// a softmax over the scores. A higher temperature flattens the priors towards uniform
+pub fn priors(scores: &[f32], temperature: f32) -> Vec<f32> {
+    let temperature = temperature.max(f32::EPSILON);
+    let max = scores.iter().cloned().fold(f32::MIN, f32::max);
+    let weights: Vec<f32> = scores
+        .iter()
+        .map(|s| ((s - max) / temperature).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / total).collect()
+}
+
+fn see_value(role: Role) -> i16 {
+    match role {
+        Role::King => SEE_KING_VALUE,
+        _ => role.reward(),
+    }
+}
+
+// SEE: what the mover nets if both sides keep recapturing on the target square with their
+// cheapest attacker, each stopping once recapturing would lose. Only normal moves are judged
+pub fn static_exchange(board: &Board, m: &Move) -> i16 {
+    let (from, to) = match *m {
+        Move::Normal { from, to, .. } => (from, to),
+        _ => return 0,
+    };
+    let mover = match board.color_at(from) {
+        Some(color) => color,
+        None => return 0,
+    };
+    let mut gains = vec![m.capture().map_or(0, see_value)];
+    let mut on_square = see_value(m.promotion().unwrap_or(m.role()));
+    let mut occupied = board.occupied() ^ Bitboard::from_square(from);
+    let mut side = !mover;
+    loop {
+        let attackers = board.attacks_to(to, side, occupied) & occupied;
+        let attacker = [
+            Role::Pawn,
+            Role::Knight,
+            Role::Bishop,
+            Role::Rook,
+            Role::Queen,
+            Role::King,
+        ]
+        .iter()
+        .filter_map(|&role| {
+            (attackers & board.by_role(role))
+                .first()
+                .map(|sq| (sq, role))
+        })
+        .next();
+        match attacker {
+            Some((square, role)) => {
+                let gain = on_square - gains[gains.len() - 1];
+                gains.push(gain);
+                occupied ^= Bitboard::from_square(square);
+                on_square = see_value(role);
+                side = !side;
+            }
+            None => break,
+        }
+    }
+    // each side may decline to recapture, working back from the last capture
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.len() - 1;
+        gains[previous] = -(-gains[previous]).max(last);
+    }
+    gains[0]
+}
+
+// how much the piece-square tables like the move, for the player making it
+fn positional_gain(board: &Board, m: &Move) -> i16 {
+    match *m {
+        Move::Normal {
+            role,
+            from,
+            to,
+            promotion,
+            ..
+        } => {
+            let color = match board.color_at(from) {
+                Some(color) => color,
+                None => return 0,
+            };
+            let before = positional_reward(from, &role.of(color));
+            let after = positional_reward(to, &promotion.unwrap_or(role).of(color));
+            color.coefficient() * (after - before)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_state::*;
+    use game_state::*;
+    use mcts::*;
+    use settings::*;
+    use setup::*;
+    use stats::*;
+    use tree_node::*;
+    use uct::*;
+    use utils::*;
+
+    fn score(fen: &str, uci: &str) -> f32 {
+        let position = parse_fen(fen);
+        let m = parse_uci(uci, &position);
+        move_score(&position, &m)
+    }
+
+    fn see(fen: &str, uci: &str) -> i16 {
+        let position = parse_fen(fen);
+        let m = parse_uci(uci, &position);
+        static_exchange(position.board(), &m)
+    }
+
+    #[test]
+    fn exchanges_on_the_target_square() {
+        // the knight is defended by a pawn, and the rook behind the queen recaptures
+        let fen = "4k3/8/4p3/3n4/8/8/3Q4/3RK3 w - - 0 1";
+        assert_eq!(see(fen, "d2d5"), 280 - 929 + 100);
+        // taking with the cheaper attacker first wins a pawn on top
+        let fen = "4k3/8/4p3/3n4/8/2N5/8/3RK3 w - - 0 1";
+        assert_eq!(see(fen, "c3d5"), 100);
+        assert_eq!(see(fen, "d1d5"), 280 - 479 + 100);
+        // a quiet move onto a square a pawn guards
+        assert_eq!(see("4k3/8/2p5/8/8/8/8/1Q2K3 w - - 0 1", "b1b5"), -929);
+    }
+
+    #[test]
+    fn scores_promising_moves_higher() {
+        let fen = "4k3/8/4p3/3n4/8/2N5/3Q4/3RK3 w - - 0 1";
+        // winning a knight with a knight beats winning it with the queen, which loses her
+        assert!(score(fen, "c3d5") > score(fen, "d2d5"));
+        assert!(score(fen, "c3d5") - score(fen, "d2d5") > LOSING_EXCHANGE_PENALTY);
+        assert!(score(fen, "d2d5") > score(fen, "e1f2"));
+        // a queening pawn checks, and scores above a quiet pawn push
+        let fen = "4k3/1P6/8/8/8/8/6P1/4K3 w - - 0 1";
+        assert!(score(fen, "b7b8q") > score(fen, "g2g3") + 800.);
+    }
+
+    #[test]
+    fn shares_out_priors() {
+        let scores = [300., 0., -300.];
+        let sharp = priors(&scores, 100.);
+        let flat = priors(&scores, 10000.);
+        assert!((sharp.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        assert!(sharp[0] > 0.9);
+        assert!(flat[0] - flat[2] < 0.1);
+        assert_eq!(priors(&[5., 5.], 200.), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn expands_the_likeliest_move_first() {
+        let settings = Settings {
+            selection_formula: SelectionFormula::Puct,
+            ..Settings::test_lib_default()
+        };
+        let mut stats: RunStats = Default::default();
+        let mut rng = seeded_rng(settings.starting_seed);
+        let position = parse_fen("4k3/8/4p3/3n4/8/2N5/3Q4/3RK3 w - - 0 1");
+        let mut root: TreeNode = TreeNode::new_root(ChessState::new(position.clone().into()));
+        root.iteration(&mut rng, &mut stats, &settings);
+        let expanded = &root.children[0];
+        assert_eq!(expanded.action, Some(parse_uci("c3d5", &position)));
+        for (_, prior) in root.action_priors(&settings) {
+            assert!(expanded.prior >= prior);
+        }
+    }
+
+    #[test]
+    fn expands_every_move_once_in_order_of_prior() {
+        let settings = Settings {
+            selection_formula: SelectionFormula::Puct,
+            ..Settings::test_lib_default()
+        };
+        let position = parse_fen("4k3/8/4p3/3n4/8/2N5/3Q4/3RK3 w - - 0 1");
+        let mut root: TreeNode = TreeNode::new_root(ChessState::new(position.into()));
+        let legal_actions = root.game.legal_actions();
+        while !root.is_fully_expanded() {
+            let child = root.expand(&settings);
+            root.children.push(child);
+        }
+        assert_eq!(root.children.len(), legal_actions.len());
+        assert_eq!(root.actions_with_no_children(), vec![]);
+        for pair in root.children.windows(2) {
+            assert!(pair[0].prior >= pair[1].prior);
+        }
+    }
+}
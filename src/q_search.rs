@@ -1,12 +1,17 @@
 use eval::*;
 use game::*;
+use hash::*;
+use playout::*;
 use settings::*;
-use shakmaty::{Chess, MoveList, Position, Setup};
+use shakmaty::MoveList;
 use stats::RunStats;
 use std::cmp::max;
+use table::*;
 
-pub fn q_search(
-    position: Chess,
+// the hash is the position's, passed down from the playout, and None without a table
+pub fn q_search<P: Game>(
+    position: P,
+    hash: Option<Hash>,
     depth: isize,
     mut alpha: Reward,
     beta: Reward,
@@ -18,6 +23,17 @@ pub fn q_search(
     if position.is_game_over() {
         return coefficient * position.outcome().unwrap().reward();
     };
+    let table = playout_table(settings, &position);
+    let mut capture_moves = MoveList::new();
+    position.capture_moves(&mut capture_moves);
+    if let (Some(table), Some(hash)) = (table, hash) {
+        match table.probe(hash, 0, alpha, beta) {
+            (Some(value), _) => return value,
+            (None, Some(best_move)) => try_first(&mut capture_moves, &best_move),
+            (None, None) => {}
+        }
+    }
+    let alpha_before = alpha;
     // TODO calling to Board.reward here because not sure if calling position.reward() adds
     // calculations to determine if we have an outcome. Check that
     let mut value = coefficient * position.board().reward(); // is this a NULL move?
@@ -25,26 +41,31 @@ pub fn q_search(
     if value > alpha {
         alpha = value
     }
-    let mut capture_moves = MoveList::new();
-    position.capture_moves(&mut capture_moves);
+    let mut best_move = None;
     for child_move in capture_moves {
         // TODO do we get capture moves if in check
         // TODO should add promotions and other big moves
         let mut child_position = position.clone();
         child_position.play_unchecked(&child_move);
+        let child_hash = hash.map(|mut hash| {
+            hash.play(&position, &child_move, &child_position);
+            hash
+        });
         // info_emojified(&child_position.board());
-        value = max(
-            -q_search(
-                child_position,
-                depth - 1,
-                -beta,
-                -alpha,
-                -coefficient,
-                stats,
-                settings,
-            ),
-            value,
-        );
+        let child_value = -backed_up(q_search(
+            child_position,
+            child_hash,
+            depth - 1,
+            pushed_down(-beta),
+            pushed_down(-alpha),
+            -coefficient,
+            stats,
+            settings,
+        ));
+        if child_value > value {
+            value = child_value;
+            best_move = Some(child_move);
+        }
         // print_value(child_move, value, depth);
 
         alpha = max(alpha, value);
@@ -52,6 +73,9 @@ pub fn q_search(
             break;
         }
     }
+    if let (Some(table), Some(hash)) = (table, hash) {
+        table.record(hash, value, best_move, 0, bound(value, alpha_before, beta));
+    }
     value
 }
 
@@ -1,12 +1,14 @@
 use shakmaty::*;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
+use std::mem;
 use twox_hash::XxHash;
 use utils::*;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct RepetitionDetector {
     map: HashMap<RepetitionPosition, u8, BuildHasherDefault<XxHash>>,
+    repeated: bool, // whether any position has occurred more than once
 }
 
 // contains the elements of the position that matter for threefold repetition according to the
@@ -18,45 +20,67 @@ pub struct RepetitionPosition {
     turn: Color,
     castles: Bitboard,
     ep_square: Option<Square>,
+    remaining_checks: Option<RemainingChecks>, // three-check only
 }
 
 impl RepetitionPosition {
-    pub fn new(position: &Chess) -> RepetitionPosition {
+    pub fn new<P: Setup>(position: &P) -> RepetitionPosition {
         RepetitionPosition {
             board: position.board().clone(),
             turn: position.turn(),
             castles: position.castling_rights(),
             ep_square: position.ep_square(),
+            remaining_checks: position.remaining_checks().cloned(),
         }
     }
 }
 
 impl RepetitionDetector {
-    pub fn new(starting_position: &Chess) -> RepetitionDetector {
+    pub fn new<P: Setup>(starting_position: &P) -> RepetitionDetector {
         let mut detector = RepetitionDetector {
             map: deterministic_hash_map(),
+            repeated: false,
         };
         detector.record(starting_position);
         detector
     }
 
-    pub fn clone_and_record(&self, position: &Chess) -> RepetitionDetector {
+    pub fn clone_and_record<P: Setup>(&self, position: &P) -> RepetitionDetector {
         let mut rd = self.clone();
         rd.record(position);
         rd
     }
 
-    pub fn is_drawn(&self, position: &Chess) -> bool {
+    pub fn is_drawn<P: Setup>(&self, position: &P) -> bool {
         *self.map.get(&RepetitionPosition::new(position)).unwrap() == 3
     }
 
-    pub fn record(&mut self, position: &Chess) {
+    // whether the position has occurred before, so one more time would draw
+    pub fn is_repeated<P: Setup>(&self, position: &P) -> bool {
+        self.map
+            .get(&RepetitionPosition::new(position))
+            .cloned()
+            .unwrap_or(0)
+            >= 2
+    }
+
+    pub fn has_repetition(&self) -> bool {
+        self.repeated
+    }
+
+    // roughly what the map allocates, for the tree's memory budget
+    pub fn heap_size(&self) -> usize {
+        self.map.capacity() * (mem::size_of::<RepetitionPosition>() + mem::size_of::<u8>() + 1)
+    }
+
+    pub fn record<P: Setup>(&mut self, position: &P) {
         let entry = self
             .map
             .entry(RepetitionPosition::new(position))
             .or_insert(0);
         *entry += 1;
         debug_assert!(*entry < 4);
+        self.repeated |= *entry >= 2;
     }
 }
 
@@ -77,10 +101,10 @@ mod test {
         let position = &Chess::default();
         let mut rd = RepetitionDetector::default();
 
-        rd.record(&position);
+        rd.record(position);
         assert_eq!(false, rd.is_drawn(position));
         // note the repetition detector registers starting position on start
-        rd.record(&position);
+        rd.record(position);
         assert_eq!(true, rd.is_drawn(position));
     }
 
@@ -0,0 +1,234 @@
+use game_state::*;
+use mcts::*;
+use rand::rngs::SmallRng;
+use settings::*;
+use stats::*;
+use std::mem;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use tree_node::*;
+use utils::*;
+
+// root parallelization: each thread grows a tree of its own from the same root, with its own
+// seed, and only the trees' roots are combined. Nothing is shared while searching, so a seed
+// always grows the same trees
+pub struct EnsemblePool<S: GameState> {
+    start: TreeNode<S>, // the root the trees grew from, with childless children
+    jobs: Vec<Sender<Job>>,
+    reports: Receiver<(usize, Report<S>)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+#[derive(Clone, Copy)]
+enum Job {
+    Iterate(Option<u64>), // with the thread's share of the node budget
+    Finish,
+}
+
+enum Report<S: GameState> {
+    Summary(TreeNode<S>, RunStats), // the root, with childless children
+    Tree(TreeNode<S>),
+    Panicked,
+}
+
+impl<S> EnsemblePool<S>
+where
+    S: GameState + Send + 'static,
+    S::Action: Send,
+{
+    pub fn new(root: &TreeNode<S>, settings: &Settings) -> EnsemblePool<S> {
+        let (report_sender, reports) = channel();
+        let mut jobs = vec![];
+        let mut workers = vec![];
+        for thread_num in 0..settings.max_threads.max(1) as usize {
+            let (job_sender, job_receiver) = channel();
+            let tree = root.clone();
+            let settings = settings.clone();
+            let reports = report_sender.clone();
+            let rng = seeded_rng(settings.starting_seed.wrapping_add(thread_num as u8));
+            workers.push(thread::spawn(move || {
+                work(thread_num, tree, &settings, rng, &job_receiver, &reports)
+            }));
+            jobs.push(job_sender);
+        }
+        EnsemblePool {
+            start: summary(root),
+            jobs,
+            reports,
+            workers,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.jobs.len()
+    }
+
+    // an iteration in each tree, returning their roots combined, with childless children
+    pub fn search(&self, stats: &mut RunStats) -> TreeNode<S> {
+        let threads = self.jobs.len() as u64;
+        let job = Job::Iterate(stats.node_budget.map(|budget| budget / threads));
+        let summaries = self.gather(job, |report| match report {
+            Report::Summary(root, run_stats) => {
+                stats.add(&run_stats);
+                root
+            }
+            _ => panic!("expected a summary"),
+        });
+        merge_roots(&self.start, summaries)
+    }
+
+    // the trees combined, each child keeping the subtree of the thread that searched it most
+    pub fn finish(self) -> TreeNode<S> {
+        let trees = self.gather(Job::Finish, |report| match report {
+            Report::Tree(tree) => tree,
+            _ => panic!("expected a tree"),
+        });
+        merge_roots(&self.start, trees)
+    }
+
+    // in thread order, however the threads finish, so the result only depends on the seed
+    fn gather<F>(&self, job: Job, mut unwrap: F) -> Vec<TreeNode<S>>
+    where
+        F: FnMut(Report<S>) -> TreeNode<S>,
+    {
+        for jobs in &self.jobs {
+            jobs.send(job).expect("sending to search thread");
+        }
+        let mut reports: Vec<Option<Report<S>>> = self.jobs.iter().map(|_| None).collect();
+        for _ in 0..self.jobs.len() {
+            let (thread_num, report) = self.reports.recv().expect("receiving from search thread");
+            if let Report::Panicked = report {
+                panic!("panicked in a search thread");
+            }
+            reports[thread_num] = Some(report);
+        }
+        reports
+            .into_iter()
+            .map(|report| unwrap(report.unwrap()))
+            .collect()
+    }
+}
+
+impl<S: GameState> Drop for EnsemblePool<S> {
+    fn drop(&mut self) {
+        // closing the channels ends the threads
+        self.jobs.clear();
+        for worker in self.workers.drain(..) {
+            worker.join().expect("panicked joining threads");
+        }
+    }
+}
+
+fn work<S: GameState>(
+    thread_num: usize,
+    mut tree: TreeNode<S>,
+    settings: &Settings,
+    mut rng: SmallRng,
+    jobs: &Receiver<Job>,
+    reports: &Sender<(usize, Report<S>)>,
+) {
+    let mut nodes_created = 0;
+    for job in jobs.iter() {
+        let report = match job {
+            Job::Iterate(node_budget) => {
+                let mut stats = RunStats {
+                    node_budget: node_budget.map(|budget| budget.saturating_sub(nodes_created)),
+                    ..Default::default()
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if tree.is_searchable() {
+                        tree.iteration(&mut rng, &mut stats, settings);
+                    }
+                }));
+                nodes_created += stats.nodes_created;
+                // depths are counted from the root's children, as in a shared tree
+                stats.mcts_max_depth = stats.mcts_max_depth.saturating_sub(1);
+                match result {
+                    Ok(()) => Report::Summary(summary(&tree), stats),
+                    Err(_) => Report::Panicked,
+                }
+            }
+            Job::Finish => Report::Tree(mem::take(&mut tree)),
+        };
+        if reports.send((thread_num, report)).is_err() {
+            return;
+        }
+    }
+}
+
+fn summary<S: GameState>(tree: &TreeNode<S>) -> TreeNode<S> {
+    let mut root = tree.clone_childless();
+    root.children = tree.children.iter().map(|c| c.clone_childless()).collect();
+    root
+}
+
+// visits and values are added up, while the bounds each tree proved are all kept. The other
+// fields, and the subtree, come from the copy of a child proven or searched the most
+fn merge_roots<S: GameState>(start: &TreeNode<S>, trees: Vec<TreeNode<S>>) -> TreeNode<S> {
+    let mut trees = trees.into_iter();
+    let mut root = trees.next().expect("merging no trees");
+    for mut tree in trees {
+        // what the trees started with, from an earlier search, is only counted once
+        discount(&mut tree, start);
+        if !tree.is_searchable() && root.is_searchable() {
+            root.state = tree.state;
+        }
+        root.n += tree.n;
+        root.q += tree.q;
+        for child in tree.children {
+            let index = root.children.iter().position(|c| c.action == child.action);
+            match index {
+                Some(index) => merge_child(&mut root.children[index], child),
+                None => {
+                    root.children.push(child);
+                    // the order it had for its moves still has this one to expand
+                    root.unexpanded = None;
+                }
+            }
+        }
+    }
+    root.update_root_based_on_children();
+    root
+}
+
+fn discount<S: GameState>(tree: &mut TreeNode<S>, start: &TreeNode<S>) {
+    tree.n -= start.n;
+    tree.q -= start.q;
+    for started in &start.children {
+        if let Some(child) = tree
+            .children
+            .iter_mut()
+            .find(|c| c.action == started.action)
+        {
+            child.n -= started.n;
+            child.q -= started.q;
+            child.amaf_n -= started.amaf_n;
+            child.amaf_q -= started.amaf_q;
+        }
+    }
+}
+
+fn merge_child<S: GameState>(merged: &mut TreeNode<S>, mut child: TreeNode<S>) {
+    let replaces = if merged.is_searchable() == child.is_searchable() {
+        child.n > merged.n
+    } else {
+        !child.is_searchable()
+    };
+    if replaces {
+        mem::swap(merged, &mut child);
+    }
+    merged.n += child.n;
+    merged.q += child.q;
+    merged.amaf_n += child.amaf_n;
+    merged.amaf_q += child.amaf_q;
+    merged.history_dependent |= child.history_dependent;
+    merged.pessimistic = merged.pessimistic.max(child.pessimistic);
+    merged.optimistic = merged.optimistic.min(child.optimistic);
+    merged.minimax = merged
+        .minimax
+        .max(merged.pessimistic)
+        .min(merged.optimistic);
+}
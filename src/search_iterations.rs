@@ -11,17 +11,30 @@ pub struct SearchIterations {
 }
 
 impl SearchStrategy for SearchIterations {
-    fn search(&self, state: State, stats: &mut RunStats, settings: &Settings) -> TreeNode {
+    fn search(
+        &self,
+        state: State,
+        stats: &mut RunStats,
+        settings: &Settings,
+        stop_signal: &StopSignal,
+    ) -> TreeNode {
+        // counted over all the threads, so e.g. UCI's go nodes isn't multiplied by the thread
+        // count
+        let limits = Limits {
+            iterations: Some(self.n_iterations as u64),
+            stop_signal: stop_signal.clone(),
+        };
+        let mut searcher = Searcher::new(&state.root, settings, limits);
         let mut new_root = state.root;
+        let mut n = 0;
 
-        for n in 0..self.n_iterations {
-            if !new_root.is_searchable() {
-                break;
-            }
-            new_root = search_threaded(new_root, stats, settings);
+        // a stopped search still needs one iteration to have a move to play
+        while new_root.is_searchable() && (n == 0 || searcher.is_searching(stats, settings)) {
+            new_root = searcher.search(new_root, stats);
             show_thinking(&new_root, &stats, &settings, n);
+            n += 1;
         }
         println!("");
-        new_root
+        searcher.finish(new_root)
     }
 }
@@ -1,25 +1,36 @@
 use search_strategy::*;
 use search_threaded::*;
 use settings::*;
+use show_thinking::*;
 use state::*;
 use stats::*;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 use tree_node::*;
 
-pub struct SearchPonder {
-    pub waiting_for_opponent: Arc<AtomicBool>,
-}
+pub struct SearchPonder;
 
 impl SearchStrategy for SearchPonder {
-    fn search(&self, state: State, stats: &mut RunStats, settings: &Settings) -> TreeNode {
+    fn search(
+        &self,
+        state: State,
+        stats: &mut RunStats,
+        settings: &Settings,
+        stop_signal: &StopSignal,
+    ) -> TreeNode {
+        let limits = Limits {
+            iterations: None,
+            stop_signal: stop_signal.clone(),
+        };
+        let mut searcher = Searcher::new(&state.root, settings, limits);
         let mut new_root = state.root;
-        while self.waiting_for_opponent.load(Ordering::Relaxed) {
-            if !new_root.is_searchable() {
-                break;
-            }
-            new_root = search_threaded(new_root, stats, &settings);
+        let mut n = 0;
+        while !stop_signal.is_stopped()
+            && new_root.is_searchable()
+            && searcher.is_searching(stats, settings)
+        {
+            new_root = searcher.search(new_root, stats);
+            show_thinking(&new_root, stats, settings, n);
+            n += 1;
         }
-        new_root
+        searcher.finish(new_root)
     }
 }
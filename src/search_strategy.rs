@@ -1,10 +1,11 @@
 use search_iterations::*;
 use search_ponder::*;
+use search_threaded::*;
 use search_time::*;
 use settings::*;
 use state::*;
 use stats::*;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tree_node::*;
@@ -13,11 +14,106 @@ use tree_node::*;
 pub enum SearchType {
     Iterations(u32),
     Time(Duration),
-    Ponder(Arc<AtomicBool>),
+    Ponder, // until stopped
+}
+
+// how the threads divide a search between them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parallelism {
+    SharedTree, // one tree, with virtual loss keeping the threads apart
+    Root,       // a tree for each thread, combined at the root
+}
+
+pub const PARALLELISMS: [Parallelism; 2] = [Parallelism::SharedTree, Parallelism::Root];
+
+impl Parallelism {
+    pub fn name(self) -> &'static str {
+        match self {
+            Parallelism::SharedTree => "tree",
+            Parallelism::Root => "root",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Parallelism> {
+        PARALLELISMS.iter().cloned().find(|p| p.name() == name)
+    }
+}
+
+pub fn parse_parallelism(name: &str) -> Result<Parallelism, String> {
+    Parallelism::from_name(name.trim()).ok_or(format!("unknown parallelism {}", name.trim()))
+}
+
+// shared with the thread running a search, so the protocol loop can end it early, e.g. for
+// xboard's ? command
+#[derive(Clone, Debug, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// what ends a search besides its time running out, checked once an iteration has finished, so a
+// search stopped before it started still has a move to play. Running out of memory doesn't, the
+// tree only stops growing
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub iterations: Option<u64>,
+    pub stop_signal: StopSignal,
+}
+
+impl Limits {
+    pub fn reached(&self, iterations: u64, stats: &RunStats, settings: &Settings) -> bool {
+        self.iterations.is_some_and(|n| iterations >= n)
+            || self.stop_signal.is_stopped()
+            || reached_max_depth(stats, settings)
+    }
 }
 
 pub trait SearchStrategy {
-    fn search(&self, state: State, stats: &mut RunStats, settings: &Settings) -> TreeNode;
+    fn search(
+        &self,
+        state: State,
+        stats: &mut RunStats,
+        settings: &Settings,
+        stop_signal: &StopSignal,
+    ) -> TreeNode;
+}
+
+pub fn reached_max_depth(stats: &RunStats, settings: &Settings) -> bool {
+    // the root's children are at depth 1
+    settings
+        .max_depth
+        .is_some_and(|max_depth| stats.mcts_max_depth + 1 >= max_depth)
+}
+
+// leave room for everything besides the tree
+const TREE_MEMORY_PERCENT: u64 = 90;
+
+// nodes are estimated from the root, since they all carry a repetition detector of about its size
+fn node_budget(root: &TreeNode, settings: &Settings) -> Option<u64> {
+    settings.memory_mb.map(|memory_mb| {
+        // the tables were allocated up front, out of the same memory
+        let table_mb = settings
+            .playout_table
+            .as_ref()
+            .map_or(0, |table| table.size_mb() as u64)
+            + settings.transpositions.as_ref().map_or(0, |table| table.mb());
+        let memory_mb = memory_mb.saturating_sub(table_mb);
+        let tree_bytes = memory_mb * 1024 * 1024 * TREE_MEMORY_PERCENT / 100;
+        let (node_size, copies) = match settings.parallelism {
+            Parallelism::SharedTree => (shared_node_size(root), 1),
+            // each thread grows its own copy of the tree
+            Parallelism::Root => (root.estimated_expanded_size(), settings.max_threads.max(1)),
+        };
+        let max_nodes = tree_bytes / node_size as u64;
+        max_nodes.saturating_sub(root.count_nodes() * copies as u64)
+    })
 }
 
 pub fn search_with_search_type(
@@ -25,24 +121,27 @@ pub fn search_with_search_type(
     search_type: SearchType,
     stats: &mut RunStats,
     settings: &Settings,
+    stop_signal: &StopSignal,
 ) -> TreeNode {
     stats.start_timer();
+    stats.node_budget = node_budget(&state.root, settings);
+    if let Some(ref table) = settings.playout_table {
+        table.new_search();
+    }
     let new_root = match search_type {
         SearchType::Iterations(n_iterations) => {
             info!("searching {} iterations", n_iterations);
             let strategy = SearchIterations { n_iterations };
-            strategy.search(state, stats, &settings)
+            strategy.search(state, stats, settings, stop_signal)
         }
         SearchType::Time(ms) => {
             info!("searching {} ms", ms.as_millis());
             let strategy = SearchTime { ms: ms };
-            strategy.search(state, stats, &settings)
+            strategy.search(state, stats, settings, stop_signal)
         }
-        SearchType::Ponder(atomic_bool) => {
-            let strategy = SearchPonder {
-                waiting_for_opponent: atomic_bool,
-            };
-            strategy.search(state, stats, &settings)
+        SearchType::Ponder => {
+            let strategy = SearchPonder;
+            strategy.search(state, stats, settings, stop_signal)
         }
     };
     stats.stop_timer();
@@ -1,74 +1,584 @@
+use game_state::*;
 use mcts::*;
+use rand::rngs::SmallRng;
+use search_ensemble::*;
+use search_strategy::*;
 use settings::*;
 use stats::*;
-use std::sync::{Arc, Mutex};
+use std::mem;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
 use std::thread::JoinHandle;
 use tree_node::*;
 use uct::*;
 use utils::*;
 
-type SafeTreeNode = Arc<Mutex<TreeNode>>;
-
-pub fn search_threaded(mut root: TreeNode, stats: &mut RunStats, settings: &Settings) -> TreeNode {
-    assert!(root.is_searchable());
-
-    let n_threads = optimal_threads(root.children.len(), settings.max_threads);
-
-    let mut new_root = root.clone_childless();
-    root.generate_missing_children(stats);
-    sort_children_by_weight(&mut root.children, new_root.n, settings);
-
-    let thread_result_handles: Vec<JoinHandle<(SafeTreeNode, Option<f32>, RunStats)>> = root
-        .children
-        .into_iter()
-        .map(|child| Arc::new(Mutex::new(child)))
-        .enumerate()
-        .map(|(thread_num, safe_thread_child)| {
-            let mut rng = seeded_rng(settings.starting_seed + thread_num as u8);
-            let mut thread_stats: RunStats = Default::default();
-            let thread_settings = settings.clone();
-
-            thread::spawn(move || {
-                let mut normalized_value = None;
-                if thread_num < n_threads {
-                    // don't do work if we're over the thread count. Wastes spawing a thread :(
-                    thread_stats.start_timer();
-                    let mut thread_child = safe_thread_child.lock().unwrap();
-                    if thread_child.is_searchable() {
-                        normalized_value = Some(thread_child.iteration(
-                            &mut rng,
-                            &mut thread_stats,
-                            &thread_settings,
-                        ));
+// the threads of a search, searching one tree together from the first call to search until the
+// limits stop them, or finish does. They never wait for each other to finish a round, while the
+// caller looks in on the tree between iterations to show it and check the time
+pub struct SearchPool<S: GameState> {
+    shared: Arc<Shared<S>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared<S: GameState> {
+    settings: Settings,
+    limits: Limits,
+    control: Mutex<Control<S>>,
+    work: Condvar, // the workers wait here for a tree to search, or for another to finish
+    progress: Condvar, // and the pool for the iterations to get on
+}
+
+struct Control<S: GameState> {
+    top: Option<Arc<SharedNode<S>>>, // with the root as its only child
+    stopped: bool,
+    in_flight: u64,
+    finished: u64,
+    shown: u64,      // the iterations finished when the caller last looked
+    stats: RunStats, // since the caller last looked
+    total: RunStats, // for the limits
+    panicked: bool,
+    shutdown: bool,
+}
+
+impl<S: GameState> Default for Control<S> {
+    fn default() -> Control<S> {
+        Control {
+            top: None,
+            stopped: false,
+            in_flight: 0,
+            finished: 0,
+            shown: 0,
+            stats: Default::default(),
+            total: Default::default(),
+            panicked: false,
+            shutdown: false,
+        }
+    }
+}
+
+impl<S: GameState> Control<S> {
+    // the iterations running are counted against the limit, so it's never overshot
+    fn can_start(&self, limits: &Limits) -> bool {
+        self.top.is_some()
+            && !self.stopped
+            && limits
+                .iterations
+                .is_none_or(|n| self.finished + self.in_flight < n)
+    }
+}
+
+// the children of a node of the shared tree, locked together. Each node is kept once, beside its
+// siblings, and its own children below it, so choosing one of them or backing up through the
+// node takes its parent's lock and then its own, always from the top down
+struct SharedNode<S: GameState> {
+    inner: Mutex<Inner<S>>,
+}
+
+struct Inner<S: GameState> {
+    children: Vec<TreeNode<S>>, // childless, but for while one of them is being worked on
+    subtrees: Vec<Option<Arc<SharedNode<S>>>>, // their children, once they have any
+    in_flight: Vec<u32>, // the iterations running through each, with virtual losses unless Empty
+}
+
+// what an iteration does at a node, once it's chosen
+enum Step {
+    Child(usize),
+    OrderMoves, // before it can expand a child
+    PlayOut,    // again, once the tree may not grow
+    Nothing,    // e.g. every child worth searching is being played out by other threads
+}
+
+impl<S: GameState> SharedNode<S> {
+    // the nodes given, their children moved below them
+    fn new(mut nodes: Vec<TreeNode<S>>) -> SharedNode<S> {
+        let subtrees = nodes
+            .iter_mut()
+            .map(|node| {
+                let children = mem::take(&mut node.children);
+                if children.is_empty() {
+                    None
+                } else {
+                    Some(Arc::new(SharedNode::new(children)))
+                }
+            })
+            .collect();
+        SharedNode {
+            inner: Mutex::new(Inner {
+                in_flight: vec![0; nodes.len()],
+                children: nodes,
+                subtrees,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner<S>> {
+        self.inner.lock().unwrap()
+    }
+
+    // once no thread is searching them
+    fn into_trees(self) -> Vec<TreeNode<S>> {
+        let inner = self.inner.into_inner().unwrap_or_else(|e| e.into_inner());
+        inner
+            .children
+            .into_iter()
+            .zip(inner.subtrees)
+            .map(|(mut node, subtree)| {
+                if let Some(subtree) = subtree {
+                    let subtree =
+                        Arc::try_unwrap(subtree).unwrap_or_else(|_| panic!("still searched"));
+                    node.children = subtree.into_trees();
+                }
+                node
+            })
+            .collect()
+    }
+
+    // the node with childless children, and the children of the one best for the player to move
+    // too, and so on, for showing the principal variation. Virtual losses are taken off
+    fn summary(&self, index: usize) -> TreeNode<S> {
+        let (mut node, best) = {
+            let inner = self.lock();
+            let mut node = inner.without_virtual_losses(index);
+            let best = inner.subtrees[index].clone().and_then(|subtree| {
+                let below = subtree.lock();
+                node.children = (0..below.children.len())
+                    .map(|i| below.without_virtual_losses(i))
+                    .collect();
+                // still being played out
+                (0..node.children.len())
+                    .filter(|&i| node.children[i].state != NodeState::Empty)
+                    .max_by_key(|&i| node.children[i].color_relative_minimax())
+                    .map(|i| (i, subtree.clone()))
+            });
+            (node, best)
+        };
+        if let Some((i, subtree)) = best {
+            node.children[i].children = subtree.summary(i).children;
+        }
+        node
+    }
+
+    // an iteration through the child at the index, which the caller has started, finishing it.
+    // Nothing is worked out under a lock besides choosing the way down and backing up, so a
+    // playout, or putting moves in order, doesn't hold up the threads searching elsewhere
+    fn iteration(
+        &self,
+        index: usize,
+        rng: &mut SmallRng,
+        stats: &mut RunStats,
+        settings: &Settings,
+        line: &mut Vec<S::Action>,
+    ) -> Option<f32> {
+        let (subtree, result) = loop {
+            let mut inner = self.lock();
+            if inner.children[index].state == NodeState::Empty {
+                let mut node = inner.children[index].clone_childless();
+                drop(inner);
+                let normalized_value = node.iteration_with_line(rng, stats, settings, line);
+                line.extend(node.action.clone());
+                self.lock().publish(index, node);
+                return Some(normalized_value);
+            }
+            let subtree = inner.subtree(index);
+            let mut below = subtree.lock();
+            match inner.step(index, &mut below, rng, stats, settings) {
+                Step::Child(child_index) => {
+                    below.start(child_index);
+                    drop(below);
+                    drop(inner);
+                    stats.iterations += 1;
+                    stats.increase_mcts_depth();
+                    let result = subtree.iteration(child_index, rng, stats, settings, line);
+                    stats.decrease_mcts_depth();
+                    break (subtree, result);
+                }
+                Step::PlayOut => {
+                    let node = inner.children[index].clone_childless();
+                    drop(below);
+                    drop(inner);
+                    stats.iterations += 1;
+                    break (subtree, Some(node.replay(stats, settings, line)));
+                }
+                Step::Nothing => {
+                    drop(below);
+                    inner.finish(index);
+                    return None;
+                }
+                Step::OrderMoves => {
+                    let node = inner.children[index].clone_childless();
+                    drop(below);
+                    drop(inner);
+                    let by_promise = node.actions_by_promise(node.game.legal_actions(), settings);
+                    let mut inner = self.lock();
+                    let mut below = subtree.lock();
+                    if inner.children[index].unexpanded.is_none() {
+                        inner.with_children(index, &mut below, |node| {
+                            node.set_unexpanded(by_promise)
+                        });
                     }
-                    thread_stats.stop_timer();
                 }
-                (safe_thread_child.clone(), normalized_value, thread_stats) // only Arc reference is cloned
+            }
+        };
+
+        let mut inner = self.lock();
+        inner.finish(index);
+        let normalized_value = result?;
+        let mut below = subtree.lock();
+        inner.with_children(index, &mut below, |node| {
+            node.backpropagate(line, normalized_value, settings);
+            line.extend(node.action.clone());
+        });
+        Some(normalized_value)
+    }
+}
+
+impl<S: GameState> Inner<S> {
+    fn subtree(&mut self, index: usize) -> Arc<SharedNode<S>> {
+        self.subtrees[index]
+            .get_or_insert_with(|| Arc::new(SharedNode::new(vec![])))
+            .clone()
+    }
+
+    // the node at the index with its children, as in a tree of its own
+    fn with_children<F, T>(&mut self, index: usize, below: &mut Inner<S>, f: F) -> T
+    where
+        F: FnOnce(&mut TreeNode<S>) -> T,
+    {
+        let node = &mut self.children[index];
+        mem::swap(&mut node.children, &mut below.children);
+        let result = f(node);
+        mem::swap(&mut node.children, &mut below.children);
+        result
+    }
+
+    // as in TreeNode::iteration_with_line, but for children another thread is playing out
+    fn step(
+        &mut self,
+        index: usize,
+        below: &mut Inner<S>,
+        rng: &mut SmallRng,
+        stats: &RunStats,
+        settings: &Settings,
+    ) -> Step {
+        if !self.children[index].is_searchable() {
+            return Step::Nothing;
+        }
+        let out_of_nodes = stats.is_out_of_nodes();
+        let busy: Vec<bool> = (0..below.children.len())
+            .map(|i| below.children[i].state == NodeState::Empty && below.in_flight[i] > 0)
+            .collect();
+        let step = self.with_children(index, below, |node| {
+            let widened =
+                node.is_widened(settings) || out_of_nodes && node.has_children_to_search();
+            if node.state == NodeState::Expandable && !widened {
+                if out_of_nodes {
+                    return Step::PlayOut;
+                }
+                if node.unexpanded.is_none() {
+                    return Step::OrderMoves;
+                }
+                let child = node.expand(settings);
+                node.children.push(child);
+                if node.is_fully_expanded() {
+                    node.state = NodeState::FullyExpanded;
+                }
+                return Step::Child(node.children.len() - 1);
+            }
+            match most_interesting_index(node, settings, rng, |i| busy[i]) {
+                Some(i) => Step::Child(i),
+                None => Step::Nothing,
+            }
+        });
+        if below.subtrees.len() < below.children.len() {
+            below.subtrees.push(None);
+            below.in_flight.push(0);
+        }
+        step
+    }
+
+    fn start(&mut self, index: usize) {
+        self.in_flight[index] += 1;
+        if self.children[index].state != NodeState::Empty {
+            self.children[index].add_virtual_loss();
+        }
+    }
+
+    fn finish(&mut self, index: usize) {
+        self.in_flight[index] -= 1;
+        self.children[index].remove_virtual_loss();
+    }
+
+    // an Empty child once it's played out. What its parent learnt of it meanwhile is kept
+    fn publish(&mut self, index: usize, mut node: TreeNode<S>) {
+        let played_out = &mut self.children[index];
+        node.amaf_n = played_out.amaf_n;
+        node.amaf_q = played_out.amaf_q;
+        *played_out = node;
+        self.in_flight[index] -= 1;
+    }
+
+    fn without_virtual_losses(&self, index: usize) -> TreeNode<S> {
+        let mut node = self.children[index].clone_childless();
+        if node.state != NodeState::Empty {
+            for _ in 0..self.in_flight[index] {
+                node.remove_virtual_loss();
+            }
+        }
+        node
+    }
+}
+
+impl<S> SearchPool<S>
+where
+    S: GameState + Send + 'static,
+    S::Action: Send,
+{
+    pub fn new(settings: &Settings, limits: Limits) -> SearchPool<S> {
+        let shared = Arc::new(Shared {
+            settings: settings.clone(),
+            limits,
+            control: Mutex::new(Default::default()),
+            work: Condvar::new(),
+            progress: Condvar::new(),
+        });
+        let workers = (0..settings.max_threads.max(1))
+            .map(|thread_num| {
+                let shared = shared.clone();
+                let rng = seeded_rng(settings.starting_seed.wrapping_add(thread_num as u8));
+                thread::spawn(move || work(&shared, rng))
             })
-        })
-        .collect();
-    let new_children: Vec<TreeNode> = thread_result_handles
-        .into_iter()
-        .map(|th| th.join().expect("panicked joining threads"))
-        .map(|(safe_thread_child, normalized_value, thread_stats)| {
-            // add stats from the children here, so we have a reference to new_root again
-            if normalized_value.is_some() {
-                stats.add(&thread_stats);
-                new_root.n += 1;
-                new_root.q += normalized_value.unwrap();
+            .collect();
+        SearchPool { shared, workers }
+    }
+
+    // a summary of the tree, once the threads have finished about an iteration each since the
+    // last one. The root is taken over the first time, and the threads search on in the
+    // meantime, until the limits stop them, or finish does
+    pub fn search(&self, root: TreeNode<S>, stats: &mut RunStats) -> TreeNode<S> {
+        let threads = self.shared.settings.max_threads.max(1) as u64;
+        let mut control = self.shared.control.lock().unwrap();
+        let top = match control.top {
+            Some(ref top) => top.clone(),
+            None => {
+                assert!(root.is_searchable());
+                let top = Arc::new(SharedNode::new(vec![root]));
+                control.top = Some(top.clone());
+                control.total.node_budget = stats.node_budget;
+                self.shared.work.notify_all();
+                top
             }
-            Arc::try_unwrap(safe_thread_child)
-                .expect("unwraping arc")
-                .into_inner()
-                .expect("unwrapping mutex")
-        })
-        .collect();
-    new_root.children = new_children;
-    new_root.update_root_based_on_children();
-    new_root
-}
-
-fn optimal_threads(n_children: usize, max_threads: u16) -> usize {
-    (n_children / 2).min(max_threads as usize).max(1)
+        };
+        let target = control.shown + threads;
+        while !control.stopped && !control.panicked && control.finished < target {
+            control = self.shared.progress.wait(control).unwrap();
+        }
+        if control.panicked {
+            drop(control);
+            panic!("panicked in a search thread");
+        }
+        control.shown = control.finished;
+        stats.add(&mem::take(&mut control.stats));
+        drop(control);
+        top.summary(0)
+    }
+
+    // until the limits stop the threads, or they run out of tree to search
+    pub fn is_searching(&self) -> bool {
+        let control = self.shared.control.lock().unwrap();
+        !control.stopped && !control.panicked
+    }
+
+    // the tree searched, once the iterations running have finished, or the root if there
+    // weren't any
+    pub fn finish(self, root: TreeNode<S>) -> TreeNode<S> {
+        let mut control = self.shared.control.lock().unwrap();
+        control.stopped = true;
+        while control.in_flight > 0 {
+            control = self.shared.progress.wait(control).unwrap();
+        }
+        let top = match control.top.take() {
+            Some(top) => top,
+            None => return root,
+        };
+        drop(control);
+        Arc::try_unwrap(top)
+            .unwrap_or_else(|_| panic!("still searched"))
+            .into_trees()
+            .pop()
+            .unwrap()
+    }
+}
+
+impl<S: GameState> Drop for SearchPool<S> {
+    fn drop(&mut self) {
+        self.shared
+            .control
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .shutdown = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().expect("panicked joining threads");
+        }
+    }
+}
+
+// what a node costs in the shared tree, with the subtree it keeps its children in, and its count
+// of the iterations running through it
+pub fn shared_node_size<S: GameState>(node: &TreeNode<S>) -> usize {
+    node.estimated_expanded_size()
+        + mem::size_of::<Option<Arc<SharedNode<S>>>>()
+        + mem::size_of::<u32>()
+        // the subtree, behind the counts of its Arc
+        + mem::size_of::<SharedNode<S>>()
+        + 2 * mem::size_of::<usize>()
+}
+
+// the root, at the top, is started like any other node
+fn root_iteration<S: GameState>(
+    top: &SharedNode<S>,
+    rng: &mut SmallRng,
+    stats: &mut RunStats,
+    settings: &Settings,
+) -> Option<f32> {
+    {
+        let mut inner = top.lock();
+        if !inner.children[0].is_searchable() {
+            return None;
+        }
+        inner.start(0);
+    }
+    top.iteration(0, rng, stats, settings, &mut vec![])
+}
+
+fn work<S: GameState>(shared: &Shared<S>, mut rng: SmallRng) {
+    loop {
+        let mut stats: RunStats = Default::default();
+        let top = {
+            let mut control = shared.control.lock().unwrap();
+            loop {
+                if control.shutdown {
+                    return;
+                }
+                if control.can_start(&shared.limits) {
+                    control.in_flight += 1;
+                    // what's left of it, which the iterations running may each take a node of
+                    stats.node_budget = control
+                        .total
+                        .node_budget
+                        .map(|budget| budget.saturating_sub(control.total.nodes_created));
+                    break control.top.clone().unwrap();
+                }
+                control = shared.work.wait(control).unwrap();
+            }
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            root_iteration(&top, &mut rng, &mut stats, &shared.settings)
+        }));
+        // the tree is only taken back once no thread holds it
+        drop(top);
+        // depths are counted from the root's children
+        stats.mcts_max_depth = stats.mcts_max_depth.saturating_sub(1);
+        let mut control = shared.control.lock().unwrap();
+        control.in_flight -= 1;
+        match result {
+            Ok(Some(_)) => {
+                control.finished += 1;
+                control.stats.add(&stats);
+                control.total.add(&stats);
+                let finished = control.finished;
+                if shared
+                    .limits
+                    .reached(finished, &control.total, &shared.settings)
+                {
+                    control.stopped = true;
+                }
+            }
+            Ok(None) if control.in_flight == 0 => {
+                // everything is proven or pruned
+                control.stopped = true;
+            }
+            Ok(None) => {
+                // until one of the other iterations has changed the tree
+                let finished = control.finished;
+                while control.finished == finished && control.in_flight > 0 && !control.shutdown {
+                    control = shared.work.wait(control).unwrap();
+                }
+            }
+            Err(_) => {
+                control.stopped = true;
+                control.panicked = true;
+            }
+        }
+        shared.work.notify_all();
+        shared.progress.notify_all();
+    }
+}
+
+// the threads of a search, however they divide it
+pub enum Searcher<S: GameState> {
+    SharedTree(SearchPool<S>),
+    // the ensemble searches in rounds of an iteration a thread, counted here
+    Root {
+        pool: EnsemblePool<S>,
+        limits: Limits,
+        iterations: u64,
+    },
+}
+
+impl<S> Searcher<S>
+where
+    S: GameState + Send + 'static,
+    S::Action: Send,
+{
+    pub fn new(root: &TreeNode<S>, settings: &Settings, limits: Limits) -> Searcher<S> {
+        match settings.parallelism {
+            Parallelism::SharedTree => Searcher::SharedTree(SearchPool::new(settings, limits)),
+            Parallelism::Root => Searcher::Root {
+                pool: EnsemblePool::new(root, settings),
+                limits,
+                iterations: 0,
+            },
+        }
+    }
+
+    // a summary of the tree searched so far. The shared tree takes over the root the first time,
+    // while the ensemble's threads carry on with trees of their own
+    pub fn search(&mut self, root: TreeNode<S>, stats: &mut RunStats) -> TreeNode<S> {
+        match *self {
+            Searcher::SharedTree(ref pool) => pool.search(root, stats),
+            Searcher::Root {
+                ref pool,
+                ref mut iterations,
+                ..
+            } => {
+                *iterations += pool.threads() as u64;
+                pool.search(stats)
+            }
+        }
+    }
+
+    // until a limit is reached. Only the shared tree's threads search in the meantime
+    pub fn is_searching(&self, stats: &RunStats, settings: &Settings) -> bool {
+        match *self {
+            Searcher::SharedTree(ref pool) => pool.is_searching(),
+            Searcher::Root {
+                ref limits,
+                iterations,
+                ..
+            } => !limits.reached(iterations, stats, settings),
+        }
+    }
+
+    // the tree to play from, and to keep for the next search
+    pub fn finish(self, root: TreeNode<S>) -> TreeNode<S> {
+        match self {
+            Searcher::SharedTree(pool) => pool.finish(root),
+            Searcher::Root { pool, .. } => pool.finish(),
+        }
+    }
 }
@@ -12,16 +12,30 @@ pub struct SearchTime {
 }
 
 impl SearchStrategy for SearchTime {
-    fn search(&self, state: State, stats: &mut RunStats, settings: &Settings) -> TreeNode {
+    fn search(
+        &self,
+        state: State,
+        stats: &mut RunStats,
+        settings: &Settings,
+        stop_signal: &StopSignal,
+    ) -> TreeNode {
+        let limits = Limits {
+            iterations: None,
+            stop_signal: stop_signal.clone(),
+        };
+        let mut searcher = Searcher::new(&state.root, settings, limits);
         let mut new_root = state.root;
+        let mut n = 0;
 
-        for n in 0..100000 {
-            if !new_root.is_searchable() || stats.elapsed() >= self.ms {
-                break;
-            }
-            new_root = search_threaded(new_root, stats, settings);
+        // a stopped search still needs one iteration to have a move to play
+        while new_root.is_searchable()
+            && stats.elapsed() < self.ms
+            && (n == 0 || searcher.is_searching(stats, settings))
+        {
+            new_root = searcher.search(new_root, stats);
             show_thinking(&new_root, &stats, &settings, n);
+            n += 1;
         }
-        new_root
+        searcher.finish(new_root)
     }
 }
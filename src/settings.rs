@@ -1,13 +1,34 @@
 use args::*;
+use game::*;
+use move_selection::*;
 use search_strategy::*;
+use std::sync::Arc;
 use std::time::*;
+use table::*;
+use transposition::*;
+use uct::*;
+use variant::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    XBoard,
+    Uci,
+}
 
 #[derive(Debug, Clone)]
 pub struct Settings {
     pub max_threads: u16,
+    pub parallelism: Parallelism,
     pub c: f32,
+    pub selection_formula: SelectionFormula,
+    pub prior_temperature: f32, // in centipawns of move score. Higher flattens the priors
+    pub widening: Option<Widening>, // None expands every move before searching any deeper
+    pub rave: Option<RaveSchedule>,
+    // MCTS statistics shared between transpositions, by every search made with these settings
+    pub transpositions: Option<Arc<TranspositionTable>>,
     pub starting_seed: u8,
     pub search_type: SearchType,
+    pub max_depth: Option<usize>,
     pub playout_depth: isize,
     // TODO move out
     pub max_tree_display_depth: Option<u8>,
@@ -15,25 +36,56 @@ pub struct Settings {
     pub print_tree: bool,
     pub log_level: String,
     pub q_search: bool,
+    pub playout_table: Option<Arc<Table>>, // alpha-beta results shared by every playout
     pub show_thinking: bool,
     pub show_thinking_freq: u32,
+    pub ponder: bool,
+    pub protocol: Protocol,
+    pub pgn_file: Option<String>, // finished games are appended here
+    pub memory_mb: Option<u64>,   // how big the tree may grow
+    pub resign_score: Option<Reward>, // resign when our score stays at or below this
+    pub resign_moves: u32,
+    pub draw_score: Option<Reward>, // offer a draw when our score stays within this of 0
+    pub draw_moves: u32,
+    pub chess960: bool, // castles are written as the king taking its rook
+    pub variant: Variant,
+    pub final_move_selection: Vec<FinalMoveSelection>, // later ones break ties
 }
 
 impl Default for Settings {
     fn default() -> Settings {
         Settings {
             max_threads: 4,
+            parallelism: Parallelism::SharedTree,
             c: 0.175,
+            selection_formula: SelectionFormula::Ucb1,
+            prior_temperature: 200.,
+            widening: None,
+            rave: None,
+            transpositions: None,
             starting_seed: 2,
             search_type: SearchType::Time(Duration::from_millis(8000)),
+            max_depth: None,
             playout_depth: 2,
             max_tree_display_depth: parse_max_tree_display_depth(),
             max_tree_display_length: parse_max_tree_display_length(),
             print_tree: parse_print_tree(),
             log_level: parse_log_level(),
             q_search: true,
+            playout_table: None,
             show_thinking: true,
             show_thinking_freq: 40, // searches
+            ponder: false,
+            protocol: Protocol::XBoard,
+            pgn_file: parse_pgn_file(),
+            memory_mb: None,
+            resign_score: None,
+            resign_moves: 3,
+            draw_score: None,
+            draw_moves: 10,
+            chess960: false,
+            variant: Variant::Standard,
+            final_move_selection: DEFAULT_FINAL_MOVE_SELECTION.to_vec(),
         }
     }
 }
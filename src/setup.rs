@@ -1,24 +1,44 @@
+use error::*;
 use shakmaty::fen::*;
 use shakmaty::san::*;
 use shakmaty::uci::*;
 use shakmaty::*;
 
-pub fn parse_fen_input(fen_str: &str) -> Result<Chess, String> {
+pub fn parse_fen_input(fen_str: &str) -> Result<Chess, EngineError> {
     info!("parsing input position:\n{}", fen_str);
     fen_str
         .parse()
-        .map_err(|e: ParseFenError| e.to_string())
-        .and_then(|f: Fen| f.position().map_err(|e: PositionError| e.to_string()))
+        .map_err(|e: ParseFenError| EngineError::InvalidPosition(e.to_string()))
+        .and_then(|f: Fen| {
+            f.position()
+                .map_err(|e: PositionError| EngineError::InvalidPosition(e.to_string()))
+        })
 }
 
-pub fn parse_uci_input(uci_str: &str, position: &Chess) -> Result<Move, String> {
+// malformed moves are illegal too, as far as the GUI is concerned. Chess960 castles can come as
+// the king taking its rook, e.g. e1h1, or as O-O and O-O-O, which is how xboard sends them
+pub fn parse_uci_input<P: Position>(uci_str: &str, position: &P) -> Result<Move, EngineError> {
+    let illegal = || EngineError::IllegalMove(uci_str.to_string());
+    if uci_str.starts_with("O-O") {
+        return uci_str
+            .parse()
+            .map_err(|_: ParseSanError| illegal())
+            .and_then(|san: San| san.to_move(position).map_err(|_: SanError| illegal()));
+    }
     uci_str
         .parse()
-        .map_err(|e: ParseUciError| e.to_string())
-        .and_then(|uci: Uci| {
-            let action = uci.to_move(position);
-            action.map_err(|e: IllegalMoveError| e.to_string())
-        })
+        .map_err(|_: ParseUciError| illegal())
+        .and_then(|uci: Uci| uci.to_move(position).map_err(|_: IllegalMoveError| illegal()))
+}
+
+// in Chess960 castles are always written as the king taking its rook, since e.g. e1g1 could be
+// a king move
+pub fn to_uci<P: Position>(position: &P, action: &Move, chess960: bool) -> Uci {
+    if chess960 {
+        Uci::from_chess960(action)
+    } else {
+        Uci::from_move(position, action)
+    }
 }
 
 pub fn parse_fen(fen_str: &str) -> Chess {
@@ -29,12 +49,12 @@ pub fn parse_fen(fen_str: &str) -> Chess {
     position
 }
 
-pub fn parse_uci(uci_str: &str, position: &Chess) -> Move {
+pub fn parse_uci<P: Position>(uci_str: &str, position: &P) -> Move {
     let uci: Uci = uci_str.parse().expect("invalid uci");
     uci.to_move(position).expect("invalid position")
 }
 
-pub fn parse_san(san_str: &str, position: &Chess) -> Move {
+pub fn parse_san<P: Position>(san_str: &str, position: &P) -> Move {
     let san: San = san_str.parse().expect("invalid san");
     san.to_move(position).expect("illegal san move")
 }
@@ -1,40 +1,114 @@
 use game::*;
 use settings::*;
+use setup::*;
 use shakmaty::san::*;
+use shakmaty::*;
 use stats::*;
 use std::fmt;
 use tree_node::*;
+use variant::*;
 
 pub fn show_thinking(root: &TreeNode, stats: &RunStats, settings: &Settings, n: u32) {
     if settings.show_thinking && n % settings.show_thinking_freq == 0 {
-        let elapsed_cs = stats.elapsed().as_millis() / 10;
-        let best_path = iterate_best_path(root);
-        let selective_depth = stats.q_max_depth;
-        let speed = stats.evals_per_second();
-        let tablebase_hits = 0;
-        println!(
-            "{} {} {} {} {} {} {} \t{}",
-            stats.comprehensive_max_depth(),
-            root.minimax,
-            elapsed_cs,
-            stats.evals,
-            selective_depth,
-            speed,
-            tablebase_hits,
-            best_path
-        );
+        match settings.protocol {
+            Protocol::XBoard => show_xboard_thinking(root, stats),
+            Protocol::Uci => show_uci_thinking(root, stats, settings.chess960),
+        }
+    }
+}
+
+fn show_xboard_thinking(root: &TreeNode, stats: &RunStats) {
+    let elapsed_cs = stats.elapsed().as_millis() / 10;
+    let best_path = iterate_best_path(root);
+    let selective_depth = stats.q_max_depth;
+    let speed = stats.evals_per_second();
+    let tablebase_hits = 0;
+    println!(
+        "{} {} {} {} {} {} {} \t{}",
+        stats.comprehensive_max_depth(),
+        xboard_score(root),
+        elapsed_cs,
+        stats.evals,
+        selective_depth,
+        speed,
+        tablebase_hits,
+        best_path
+    );
+}
+
+fn show_uci_thinking(root: &TreeNode, stats: &RunStats, chess960: bool) {
+    let best_path = iterate_best_path(root);
+    println!(
+        "info depth {} seldepth {} score {} nodes {} nps {} time {} pv {}",
+        stats.comprehensive_max_depth(),
+        stats.max_depth(),
+        uci_score(root),
+        stats.evals,
+        stats.evals_per_second(),
+        stats.elapsed().as_millis(),
+        best_path.to_uci_string(chess960)
+    );
+}
+
+// UCI scores are relative to the side to move. Mates are reported in moves, not plies
+fn uci_score(root: &TreeNode) -> String {
+    let score = relative_score(root);
+    match mate_moves(score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", score),
     }
 }
 
+// xboard wants mates as 100000 + N when we mate in N moves, and -100000 - N when we're mated
+fn xboard_score(root: &TreeNode) -> i32 {
+    let score = relative_score(root);
+    match mate_moves(score) {
+        Some(moves) if score > 0 => 100000 + moves,
+        Some(moves) => -100000 + moves,
+        None => score as i32,
+    }
+}
+
+fn relative_score(root: &TreeNode) -> Reward {
+    root.minimax * root.player_to_move().coefficient()
+}
+
+// negative when the side to move is getting mated
+fn mate_moves(score: Reward) -> Option<i32> {
+    mate_plies(score).map(|plies| {
+        let moves = (plies as i32 + 1) / 2;
+        if score > 0 {
+            moves
+        } else {
+            -moves
+        }
+    })
+}
+
 #[derive(Default, Debug)]
 struct BestPath {
-    path: Vec<SanPlus>,
+    position: VariantPosition,
+    moves: Vec<Move>,
+}
+
+impl BestPath {
+    fn to_uci_string(&self, chess960: bool) -> String {
+        let mut position = self.position.clone();
+        let mut ucis: Vec<String> = vec![];
+        for action in self.moves.iter() {
+            ucis.push(to_uci(&position, action, chess960).to_string());
+            position.make_move(action);
+        }
+        ucis.join(" ")
+    }
 }
 
 fn iterate_best_path(root: &TreeNode) -> BestPath {
-    let mut best_path: BestPath = Default::default();
+    let mut best_path = BestPath {
+        position: root.game.position.clone(),
+        moves: vec![],
+    };
     let mut head = root;
-    let mut position = root.position.clone();
     while !head.children.is_empty() {
         head = head
             .children
@@ -44,20 +118,51 @@ fn iterate_best_path(root: &TreeNode) -> BestPath {
                     .cmp(&n2.color_relative_minimax())
             })
             .unwrap();
-        let action = head.action.clone().unwrap();
-        best_path
-            .path
-            .push(SanPlus::from_move(position.clone(), &action));
-        position.make_move(&action);
+        best_path.moves.push(head.action.clone().unwrap());
     }
     best_path
 }
 
 impl fmt::Display for BestPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for action in self.path.iter() {
-            f.write_fmt(format_args!("{} ", action))?;
+        let mut position = self.position.clone();
+        for action in self.moves.iter() {
+            f.write_fmt(format_args!(
+                "{} ",
+                SanPlus::from_move(position.clone(), action)
+            ))?;
+            position.make_move(action);
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_state::*;
+
+    fn root_scoring(fen: &str, minimax: Reward) -> TreeNode {
+        let mut root = TreeNode::new_root(ChessState::new(parse_fen(fen).into()));
+        root.minimax = minimax;
+        root
+    }
+
+    #[test]
+    fn reports_mates_in_moves() {
+        let white = "4k3/Q7/5K2/8/8/8/8/8 w - - 0 1";
+        let black = "4k3/Q7/5K2/8/8/8/8/8 b - - 0 1";
+        let mate_in_1 = root_scoring(white, MAX_REWARD - 1);
+        assert_eq!(uci_score(&mate_in_1), "mate 1");
+        assert_eq!(xboard_score(&mate_in_1), 100001);
+        let mate_in_3 = root_scoring(black, MIN_REWARD + 5);
+        assert_eq!(uci_score(&mate_in_3), "mate 3");
+        assert_eq!(xboard_score(&mate_in_3), 100003);
+        let mated_in_2 = root_scoring(white, MIN_REWARD + 4);
+        assert_eq!(uci_score(&mated_in_2), "mate -2");
+        assert_eq!(xboard_score(&mated_in_2), -100002);
+        let ahead = root_scoring(black, -250);
+        assert_eq!(uci_score(&ahead), "cp 250");
+        assert_eq!(xboard_score(&ahead), 250);
+    }
+}
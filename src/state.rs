@@ -1,4 +1,6 @@
+use chess_state::*;
 use game::*;
+use history::*;
 use log::*;
 use search_strategy::*;
 use settings::*;
@@ -7,6 +9,7 @@ use stats::*;
 use std::time::Duration;
 use time_remaining::*;
 use tree_node::*;
+use variant::*;
 
 #[derive(Default)]
 pub struct State {
@@ -16,9 +19,9 @@ pub struct State {
 }
 
 impl State {
-    pub fn from_position(position: Chess) -> State {
+    pub fn from_position<P: Into<VariantPosition>>(position: P) -> State {
         State {
-            root: TreeNode::new_root(position.clone()),
+            root: TreeNode::new_root(ChessState::new(position.into())),
             ..Default::default()
         }
     }
@@ -28,31 +31,20 @@ impl State {
         search_type: SearchType,
         stats: &mut RunStats,
         settings: &Settings,
+        stop_signal: &StopSignal,
     ) -> State {
         let time_remaining = self.time_remaining.clone();
         let opponent_time_remaining = self.opponent_time_remaining.clone();
         State {
-            root: search_with_search_type(self, search_type, stats, settings),
+            root: search_with_search_type(self, search_type, stats, settings, stop_signal),
             time_remaining: time_remaining,
             opponent_time_remaining: opponent_time_remaining,
         }
     }
 
-    pub fn best_move(&self) -> Move {
-        // TODO try the equation from the MCTS-Solver paper
+    pub fn best_move(&self, settings: &Settings) -> Move {
         self.root
-            .children
-            .iter()
-            .max_by(|c1, c2| {
-                if c1.best_child_sort_use_minimax() || c2.best_child_sort_use_minimax() {
-                    c1.best_child_sort_minimax()
-                        .cmp(&c2.best_child_sort_minimax())
-                } else {
-                    c1.best_child_sort_n()
-                        .partial_cmp(&c2.best_child_sort_n())
-                        .unwrap()
-                }
-            })
+            .best_child(&settings.final_move_selection)
             .and_then(|c| c.action.clone())
             .expect("no best child to choose from")
     }
@@ -60,19 +52,38 @@ impl State {
     pub fn make_move(self, action: &Move) -> State {
         let time_remaining = self.time_remaining.clone();
         let opponent_time_remaining = self.opponent_time_remaining.clone();
-        let mut position = self.position();
-        let new_root = self.find_child_by_action(action);
+        let mut game = self.root.game.clone();
+        let new_root = self.find_child_by_action(action).map(|mut root| {
+            // proven by a transposition, but we still need children to choose a move from
+            if root.state == NodeState::FullySearched && root.children.is_empty() {
+                root.state = NodeState::Expandable;
+            }
+            root
+        });
         State {
             root: new_root.unwrap_or_else(|| {
                 warn!("child by action not found");
-                position.play_safe(action);
-                TreeNode::new_root(position)
+                game.position.play_safe(action);
+                game.repetition_detector.record(&game.position);
+                TreeNode::new_root(game)
             }),
             time_remaining,
             opponent_time_remaining,
         }
     }
 
+    pub fn take_back(self, history: &mut GameHistory) -> State {
+        let time_remaining = self.time_remaining.clone();
+        let opponent_time_remaining = self.opponent_time_remaining;
+        State {
+            root: history
+                .take_back(self.root)
+                .expect("no moves to take back"),
+            time_remaining,
+            opponent_time_remaining,
+        }
+    }
+
     pub fn find_child_by_action(self, action: &Move) -> Option<TreeNode> {
         let found = self
             .root
@@ -101,7 +112,7 @@ impl State {
     }
 
     pub fn is_checkmate(&self) -> bool {
-        self.root.is_checkmate()
+        self.root.game.position.is_checkmate()
     }
 
     pub fn last_action(&self) -> Move {
@@ -109,7 +120,7 @@ impl State {
     }
 
     pub fn turn(&self) -> Color {
-        self.root.turn()
+        self.root.game.turn()
     }
 
     pub fn q(&self) -> f32 {
@@ -117,11 +128,11 @@ impl State {
     }
 
     pub fn display_move_num(&self) -> String {
-        self.root.display_move_num()
+        self.root.game.position.display_move_num()
     }
 
-    pub fn position(&self) -> Chess {
-        self.root.position.clone()
+    pub fn position(&self) -> VariantPosition {
+        self.root.game.position.clone()
     }
 
     pub fn is_game_over(&self) -> bool {
@@ -132,9 +143,9 @@ impl State {
         self.root.minimax
     }
 
-    pub fn record_test_repetitions(&mut self, repetition_positions: Vec<Chess>) {
+    pub fn record_test_repetitions<P: Setup>(&mut self, repetition_positions: Vec<P>) {
         for r in repetition_positions {
-            self.root.repetition_detector.record(&r)
+            self.root.game.repetition_detector.record(&r)
         }
     }
 }
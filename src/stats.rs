@@ -13,6 +13,11 @@ pub struct RunStats {
     pub mcts_max_depth: usize,
     pub playout_max_depth: usize,
     pub q_max_depth: usize,
+    pub ponder_hits: u64,
+    pub ponder_misses: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub node_budget: Option<u64>, // how many nodes the tree may grow by before it runs out of memory
 }
 
 impl RunStats {
@@ -25,6 +30,10 @@ impl RunStats {
         self.mcts_max_depth = self.mcts_max_depth.max(run_stats.mcts_max_depth);
         self.playout_max_depth = self.playout_max_depth.max(run_stats.playout_max_depth);
         self.q_max_depth = self.q_max_depth.max(run_stats.q_max_depth);
+        self.ponder_hits += run_stats.ponder_hits;
+        self.ponder_misses += run_stats.ponder_misses;
+        self.tt_probes += run_stats.tt_probes;
+        self.tt_hits += run_stats.tt_hits;
     }
 
     pub fn start_timer(&mut self) {
@@ -49,6 +58,21 @@ impl RunStats {
         self.evals.checked_div(self.playout_leaves).unwrap_or(0) * 100
     }
 
+    // how often a new node found its position in the transposition table, in percent
+    pub fn tt_hit_rate(&self) -> u64 {
+        (self.tt_hits * 100)
+            .checked_div(self.tt_probes)
+            .unwrap_or(0)
+    }
+
+    // then only the root is still expanded, so there are always moves to choose from
+    pub fn is_out_of_nodes(&self) -> bool {
+        self.mcts_depth > 0
+            && self
+                .node_budget
+                .is_some_and(|budget| self.nodes_created >= budget)
+    }
+
     pub fn increase_mcts_depth(&mut self) {
         self.mcts_depth += 1;
         self.mcts_max_depth = self.mcts_max_depth.max(self.mcts_depth);
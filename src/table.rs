@@ -1,11 +1,14 @@
-use _move::Move;
-use board::*;
-use rand::{Rng, SeedableRng, StdRng};
+use game::*;
+use hash::*;
+use settings::*;
+use shakmaty::*;
 use std::collections::HashSet;
+use std::fmt;
 use std::mem;
-use types::*;
-use util::lsb;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
 
+// what a playout or quiescence search learned about a position, for the next one to reach it
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Bound {
@@ -14,123 +17,155 @@ pub enum Bound {
     Upper = 2,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Entry {
-    pub score: i32,
-    pub best_move: Move,
-    pub hash16: u16,
+    pub score: Reward, // for the player to move
+    pub best_move: Option<Move>,
+    pub hash32: u32,
     pub depth: u8,
-    pub info: u8, // Upper 2 bits -> bound, lowest bit -> ancient
+    pub info: u8,       // Upper 2 bits -> bound
+    pub generation: u8, // the search that stored it
 }
 
 impl Entry {
     const NULL: Entry = Entry {
         score: 0,
-        best_move: Move::NULL,
-        hash16: 0,
+        best_move: None,
+        hash32: 0,
         depth: 0,
         info: 0,
+        generation: 0,
     };
 
     pub fn is_empty(&self) -> bool {
         *self == Entry::NULL
     }
 
-    pub fn ancient(&self) -> bool {
-        self.info & 0b1 != 0
-    }
-
     pub fn bound(&self) -> Bound {
-        unsafe { mem::transmute(self.info >> 6) }
+        match self.info >> 6 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        }
     }
 
     pub fn compare(&self, hash: &Hash) -> bool {
-        self.hash16 == hash.sub()
+        self.hash32 == hash.sub()
     }
 }
 
+// shared by the playouts of every thread, each entry locked on its own
 pub struct Table {
-    pub entries: Vec<Entry>,
+    pub entries: Vec<Mutex<Entry>>,
+    generation: AtomicU8,
+}
+
+// the table, unless the position has pockets, which aren't hashed
+pub fn playout_table<'a, P: Game>(settings: &'a Settings, position: &P) -> Option<&'a Table> {
+    settings
+        .playout_table
+        .as_deref()
+        .filter(|_| position.pockets().is_none())
 }
 
 impl Table {
     pub fn empty(size: usize) -> Self {
         Table {
-            entries: vec![Entry::NULL; size],
+            entries: (0..size.max(1)).map(|_| Mutex::new(Entry::NULL)).collect(),
+            generation: AtomicU8::new(0),
         }
     }
 
     pub fn empty_mb(size_mb: usize) -> Self {
-        Table::empty(size_mb * 1024 * 1024 / mem::size_of::<Entry>())
+        Table::empty(size_mb * 1024 * 1024 / mem::size_of::<Mutex<Entry>>())
     }
 
-    pub fn probe(&self, hash: Hash, depth: u8, alpha: i32, beta: i32) -> (Option<i32>, Move) {
-        let entry = &self.entries[hash.val as usize % self.size()];
+    // rounded, so it's what empty_mb was given
+    pub fn size_mb(&self) -> usize {
+        (self.size() * mem::size_of::<Mutex<Entry>>() + 512 * 1024) / (1024 * 1024)
+    }
+
+    // the score if it's good enough for this search, and the best move to try first if not
+    pub fn probe(
+        &self,
+        hash: Hash,
+        depth: u8,
+        alpha: Reward,
+        beta: Reward,
+    ) -> (Option<Reward>, Option<Move>) {
+        let entry = self.entry(hash).lock().unwrap();
 
         if !entry.is_empty() && entry.compare(&hash) {
-            // if entry.depth() == depth {
-            //     println!("{} {:?} d = {} a = {} b = {}", entry.score, entry.bound(), depth, alpha, beta);
-            // }
             if entry.depth >= depth
                 && match entry.bound() {
                     Bound::Lower => entry.score >= beta,
                     Bound::Upper => entry.score <= alpha,
                     Bound::Exact => true,
-                } {
-                return (Some(entry.score), Move::NULL);
+                }
+            {
+                return (Some(entry.score), entry.best_move.clone());
             }
 
-            return (None, entry.best_move);
+            return (None, entry.best_move.clone());
         }
-        (None, Move::NULL)
+        (None, None)
     }
 
     pub fn best_move(&self, hash: Hash) -> Option<Move> {
-        let entry = &self.entries[hash.val as usize % self.size()];
+        let entry = self.entry(hash).lock().unwrap();
 
-        if !entry.is_empty() && entry.compare(&hash) && entry.best_move != Move::NULL {
-            return Some(entry.best_move);
+        if !entry.is_empty() && entry.compare(&hash) {
+            return entry.best_move.clone();
         }
         None
     }
 
-    pub fn record(&mut self, board: &Board, score: i32, best_move: Move, depth: u8, bound: Bound) {
-        let size = self.size();
-        let entry = &mut self.entries[board.hash.val as usize % size];
+    pub fn record(
+        &self,
+        hash: Hash,
+        score: Reward,
+        best_move: Option<Move>,
+        depth: u8,
+        bound: Bound,
+    ) {
+        let generation = self.generation();
+        let mut entry = self.entry(hash).lock().unwrap();
 
-        if entry.is_empty() || entry.depth <= depth || entry.ancient() {
+        if entry.is_empty() || entry.depth <= depth || entry.generation != generation {
             let info = (bound as u8) << 6;
             *entry = Entry {
-                score: score,
-                best_move: best_move,
-                hash16: board.hash.sub(),
-                depth: depth,
-                info: info,
+                score,
+                best_move,
+                hash32: hash.sub(),
+                depth,
+                info,
+                generation,
             };
         }
     }
 
-    pub fn pv(&self, board: &Board) -> Vec<Move> {
+    pub fn pv<P: Game>(&self, position: &P) -> Vec<Move> {
         let mut pv = Vec::new();
         let mut visited = HashSet::new();
-        self.pv_cycle_track(*board, &mut pv, &mut visited);
+        self.pv_cycle_track(position.clone(), &mut pv, &mut visited);
         pv
     }
 
-    pub fn pv_cycle_track(
+    pub fn pv_cycle_track<P: Game>(
         &self,
-        mut board: Board,
+        mut position: P,
         pv: &mut Vec<Move>,
         visited: &mut HashSet<Hash>,
     ) {
-        let mv = self.best_move(board.hash);
+        let mv = self.best_move(Hash::generate(&position));
 
-        if let Some(m) = mv {
+        // the bits compared can collide, so the move might not be one we can play
+        if let Some(m) = mv.filter(|m| position.is_legal(m)) {
+            position.play_unchecked(&m);
             pv.push(m);
-            board.make_move(m);
 
-            if visited.insert(board.hash) {
-                self.pv_cycle_track(board, pv, visited);
+            if visited.insert(Hash::generate(&position)) {
+                self.pv_cycle_track(position, pv, visited);
             }
         }
     }
@@ -139,25 +174,115 @@ impl Table {
         self.entries.len()
     }
 
-    pub fn set_ancient(&mut self) -> usize {
-        let mut num = 0;
-        for entry in &mut self.entries {
-            if !entry.is_empty() {
-                num += 1;
-                entry.info |= 0b1;
-            }
+    // called as each search starts, so what earlier ones stored gives way to what this one finds
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            *entry.lock().unwrap() = Entry::NULL;
         }
-        num
+    }
+
+    fn entry(&self, hash: Hash) -> &Mutex<Entry> {
+        &self.entries[hash.val as usize % self.size()]
+    }
+}
+
+// the settings are logged, and every entry would be
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Table {{ entries: {} }}", self.size())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use setup::*;
+    use std::sync::Arc;
+
+    fn hash(fen: &str) -> Hash {
+        Hash::generate(&parse_fen(fen))
+    }
+
+    const FEN: &str = "4k3/8/8/8/3P4/8/8/4K3 w - -";
+
+    #[test]
+    fn add_and_retreive() {
+        let table = Table::empty_mb(1);
+        let position = parse_fen(FEN);
+        let push = parse_uci("d4d5", &position);
+        assert_eq!(table.probe(hash(FEN), 0, -100, 100), (None, None));
+        table.record(hash(FEN), 50, Some(push.clone()), 2, Bound::Exact);
+        assert_eq!(
+            table.probe(hash(FEN), 2, -100, 100),
+            (Some(50), Some(push.clone()))
+        );
+        // not searched deep enough, but the move is still worth trying first
+        assert_eq!(
+            table.probe(hash(FEN), 3, -100, 100),
+            (None, Some(push.clone()))
+        );
+        assert_eq!(table.best_move(hash(FEN)), Some(push.clone()));
+        assert_eq!(table.pv(&position), vec![push]);
+        assert_eq!(table.size_mb(), 1);
+        table.clear();
+        assert_eq!(table.best_move(hash(FEN)), None);
+    }
+
+    #[test]
+    fn cuts_off_by_bound() {
+        let table = Table::empty(64);
+        table.record(hash(FEN), 50, None, 1, Bound::Lower);
+        assert_eq!(table.probe(hash(FEN), 1, -100, 40).0, Some(50));
+        assert_eq!(table.probe(hash(FEN), 1, -100, 60).0, None);
+        table.record(hash(FEN), 50, None, 1, Bound::Upper);
+        assert_eq!(table.probe(hash(FEN), 1, 60, 100).0, Some(50));
+        assert_eq!(table.probe(hash(FEN), 1, 40, 100).0, None);
+    }
+
+    #[test]
+    fn skipped_for_positions_with_pockets() {
+        let settings = Settings {
+            playout_table: Some(Arc::new(Table::empty(64))),
+            ..Default::default()
+        };
+        assert!(playout_table(&settings, &Chess::default()).is_some());
+        assert!(playout_table(&settings, &variants::Crazyhouse::default()).is_none());
+    }
+
+    #[test]
+    fn prefers_deeper_entries_until_they_are_ancient() {
+        let table = Table::empty(64);
+        table.record(hash(FEN), 50, None, 3, Bound::Exact);
+        table.record(hash(FEN), 10, None, 1, Bound::Exact);
+        assert_eq!(table.probe(hash(FEN), 1, -100, 100).0, Some(50));
+        table.new_search();
+        table.record(hash(FEN), 10, None, 1, Bound::Exact);
+        assert_eq!(table.probe(hash(FEN), 1, -100, 100).0, Some(10));
+    }
+
+    #[test]
+    fn keys_by_hash() {
+        let table = Table::empty(1);
+        table.record(hash(FEN), 50, None, 0, Bound::Exact);
+        let other = "4k3/8/8/3P4/8/8/8/4K3 w - -";
+        assert_eq!(table.probe(hash(other), 0, -100, 100), (None, None));
+    }
 
     #[test]
-    fn add_and_retreive(){
-        init();
-        let table = Table::empty_mb(10);
-        table.record(
+    fn checks_more_than_the_top_16_bits() {
+        let table = Table::empty(1);
+        table.record(Hash { val: 0xabcd << 48 }, 50, None, 0, Bound::Exact);
+        let other = Hash {
+            val: 0xabcd << 48 | 1 << 32,
+        };
+        assert_eq!(table.probe(other, 0, -100, 100), (None, None));
     }
 }
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+// assume the game lasts this many more moves when the time control doesn't tell us
+pub const DEFAULT_MOVES_TO_GO: u32 = 30;
+// leave some time for communicating with the GUI so we don't lose on time
+pub const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeControl {
+    // level 40 5 0: a fixed number of moves in each session
+    Conventional {
+        moves_per_session: u32,
+        base: Duration,
+        increment: Duration,
+    },
+    // level 0 2 12: the whole game, plus an increment per move
+    Incremental { base: Duration, increment: Duration },
+    // st 10: a fixed time for every move
+    FixedTime(Duration),
+}
+
+impl TimeControl {
+    // parses the arguments of the xboard level command: MPS BASE INC, where BASE is minutes or
+    // minutes:seconds and INC is seconds
+    pub fn parse_level(args: &str) -> Result<TimeControl, String> {
+        let tokens: Vec<&str> = args.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return Err(format!("expected MPS BASE INC, got {}", args));
+        }
+        let moves_per_session = tokens[0]
+            .parse::<u32>()
+            .map_err(|_| format!("invalid moves per session {}", tokens[0]))?;
+        let base = parse_base(tokens[1])?;
+        let increment = parse_seconds(tokens[2])?;
+        if moves_per_session == 0 {
+            Ok(TimeControl::Incremental { base, increment })
+        } else {
+            Ok(TimeControl::Conventional {
+                moves_per_session,
+                base,
+                increment,
+            })
+        }
+    }
+
+    // parses the argument of the xboard st command
+    pub fn parse_fixed_time(seconds: &str) -> Result<TimeControl, String> {
+        Ok(TimeControl::FixedTime(parse_seconds(seconds)?))
+    }
+
+    // how long to search the next move, given our clock and how many moves we've already made
+    pub fn time_for_move(&self, time_remaining: Option<Duration>, moves_played: u32) -> Duration {
+        match *self {
+            TimeControl::FixedTime(time) => time.checked_sub(SAFETY_MARGIN).unwrap_or(time),
+            TimeControl::Conventional {
+                moves_per_session,
+                base,
+                increment,
+            } => {
+                let moves_to_go = moves_per_session - moves_played % moves_per_session;
+                allocate(time_remaining.unwrap_or(base), moves_to_go, increment)
+            }
+            TimeControl::Incremental { base, increment } => {
+                allocate(time_remaining.unwrap_or(base), DEFAULT_MOVES_TO_GO, increment)
+            }
+        }
+    }
+}
+
+fn allocate(time_remaining: Duration, moves_to_go: u32, increment: Duration) -> Duration {
+    let usable = time_remaining
+        .checked_sub(SAFETY_MARGIN)
+        .unwrap_or_else(|| Duration::from_millis(0));
+    (usable / moves_to_go + increment).min(usable)
+}
+
+fn parse_base(base: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid base time {}", base);
+    let parts: Vec<&str> = base.split(':').collect();
+    let (minutes, seconds) = match parts.as_slice() {
+        [minutes] => (minutes.parse::<u64>().map_err(|_| invalid())?, 0),
+        [minutes, seconds] => (
+            minutes.parse::<u64>().map_err(|_| invalid())?,
+            seconds.parse::<u64>().map_err(|_| invalid())?,
+        ),
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(minutes * 60 + seconds))
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|s| *s >= 0.)
+        .map(|s| Duration::from_millis((s * 1000.) as u64))
+        .ok_or(format!("invalid seconds {}", seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_conventional_level() {
+        assert_eq!(
+            TimeControl::parse_level("40 5 0"),
+            Ok(TimeControl::Conventional {
+                moves_per_session: 40,
+                base: Duration::from_secs(300),
+                increment: Duration::from_secs(0),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_incremental_level() {
+        assert_eq!(
+            TimeControl::parse_level("0 2 12"),
+            Ok(TimeControl::Incremental {
+                base: Duration::from_secs(120),
+                increment: Duration::from_secs(12),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_level_with_seconds_and_fractional_increment() {
+        assert_eq!(
+            TimeControl::parse_level("0 0:30 0.5"),
+            Ok(TimeControl::Incremental {
+                base: Duration::from_secs(30),
+                increment: Duration::from_millis(500),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_level() {
+        assert!(TimeControl::parse_level("40 5").is_err());
+        assert!(TimeControl::parse_level("40 five 0").is_err());
+        assert!(TimeControl::parse_level("40 5 -1").is_err());
+    }
+
+    #[test]
+    fn parses_fixed_time() {
+        assert_eq!(
+            TimeControl::parse_fixed_time("10"),
+            Ok(TimeControl::FixedTime(Duration::from_secs(10)))
+        );
+    }
+
+    #[test]
+    fn fixed_time_uses_the_whole_move_time() {
+        let time_control = TimeControl::FixedTime(Duration::from_secs(10));
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_secs(1)), 12),
+            Duration::from_millis(9950)
+        );
+    }
+
+    #[test]
+    fn conventional_spreads_time_over_moves_to_go() {
+        let time_control = TimeControl::parse_level("40 5 0").unwrap();
+        assert_eq!(
+            time_control.time_for_move(None, 0),
+            Duration::from_millis(299950) / 40
+        );
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_millis(10050)), 38),
+            Duration::from_millis(5000)
+        );
+        // a new session starts after 40 moves
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_millis(40050)), 40),
+            Duration::from_millis(1000)
+        );
+    }
+
+    #[test]
+    fn incremental_adds_increment() {
+        let time_control = TimeControl::parse_level("0 2 12").unwrap();
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_millis(60050)), 10),
+            Duration::from_millis(2000 + 12000)
+        );
+    }
+
+    #[test]
+    fn never_allocates_more_than_remaining() {
+        let time_control = TimeControl::parse_level("0 2 12").unwrap();
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_millis(1050)), 10),
+            Duration::from_millis(1000)
+        );
+        assert_eq!(
+            time_control.time_for_move(Some(Duration::from_millis(10)), 10),
+            Duration::from_millis(0)
+        );
+    }
+}
@@ -14,6 +14,10 @@ impl TimeRemaining {
         }
     }
 
+    pub fn remaining(&self) -> Duration {
+        self.recalculate_from_now().remaining
+    }
+
     pub fn recalculate_from_now(&self) -> TimeRemaining {
         let now = Instant::now();
         let time_spent = now - self.start;
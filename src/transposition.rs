@@ -0,0 +1,146 @@
+use game::*;
+use std::fmt;
+use std::mem;
+use std::sync::Mutex;
+
+// what the search has learned about a position, shared between the nodes it's reached by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    pub key: u64,
+    pub n: u32,
+    pub q: f32,
+    pub value: Reward,
+    pub minimax: Reward,
+    pub pessimistic: Reward,
+    pub optimistic: Reward,
+    pub proven: bool, // searched as far as it needs to be
+}
+
+// a fixed number of slots, indexed by key and locked one at a time, so the search threads rarely
+// wait on each other
+pub struct TranspositionTable {
+    slots: Vec<Mutex<Option<Entry>>>,
+}
+
+impl TranspositionTable {
+    pub fn new(n_slots: usize) -> TranspositionTable {
+        TranspositionTable {
+            slots: (0..n_slots.max(1)).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    pub fn with_mb(mb: u64) -> TranspositionTable {
+        let slot_size = mem::size_of::<Mutex<Option<Entry>>>() as u64;
+        TranspositionTable::new((mb * 1024 * 1024 / slot_size) as usize)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // rounded, so it's what with_mb was given
+    pub fn mb(&self) -> u64 {
+        let bytes = (self.capacity() * mem::size_of::<Mutex<Option<Entry>>>()) as u64;
+        (bytes + 512 * 1024) / (1024 * 1024)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let slot = self.slot(key).lock().unwrap();
+        slot.filter(|entry| entry.key == key)
+    }
+
+    // the better searched of two positions keeps the slot. A node that adopted an entry starts
+    // with fewer visits than it, so it only replaces it once it's searched more or proven
+    pub fn store(&self, entry: Entry) {
+        let mut slot = self.slot(entry.key).lock().unwrap();
+        let replace = match *slot {
+            Some(old) => entry.n >= old.n || (old.key == entry.key && entry.proven),
+            None => true,
+        };
+        if replace {
+            *slot = Some(entry);
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            *slot.lock().unwrap() = None;
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Mutex<Option<Entry>> {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+}
+
+// the settings are logged, and every slot would be
+impl fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TranspositionTable {{ slots: {} }}", self.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64, n: u32) -> Entry {
+        Entry {
+            key,
+            n,
+            q: n as f32 / 2.,
+            value: 10,
+            minimax: 20,
+            pessimistic: MIN_REWARD,
+            optimistic: MAX_REWARD,
+            proven: false,
+        }
+    }
+
+    #[test]
+    fn finds_what_it_stored() {
+        let table = TranspositionTable::new(16);
+        assert_eq!(table.probe(3), None);
+        table.store(entry(3, 5));
+        assert_eq!(table.probe(3), Some(entry(3, 5)));
+        assert_eq!(table.probe(19), None); // same slot, another position
+        table.store(entry(3, 7));
+        assert_eq!(table.probe(3), Some(entry(3, 7)));
+        table.clear();
+        assert_eq!(table.probe(3), None);
+    }
+
+    #[test]
+    fn keeps_the_better_searched_position() {
+        let table = TranspositionTable::new(16);
+        table.store(entry(3, 5));
+        table.store(entry(19, 4));
+        assert_eq!(table.probe(3), Some(entry(3, 5)));
+        assert_eq!(table.probe(19), None);
+        table.store(entry(19, 6));
+        assert_eq!(table.probe(3), None);
+        assert_eq!(table.probe(19), Some(entry(19, 6)));
+    }
+
+    #[test]
+    fn keeps_the_better_searched_entry_until_proven() {
+        let table = TranspositionTable::new(16);
+        table.store(entry(3, 5));
+        table.store(entry(3, 2));
+        assert_eq!(table.probe(3), Some(entry(3, 5)));
+        let proven = Entry {
+            proven: true,
+            ..entry(3, 2)
+        };
+        table.store(proven);
+        assert_eq!(table.probe(3), Some(proven));
+    }
+
+    #[test]
+    fn sizes_in_megabytes() {
+        let table = TranspositionTable::with_mb(1);
+        assert!(table.capacity() > 1000);
+        assert!(table.capacity() * mem::size_of::<Mutex<Option<Entry>>>() <= 1024 * 1024);
+        assert_eq!(TranspositionTable::with_mb(16).mb(), 16);
+    }
+}
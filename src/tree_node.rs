@@ -1,8 +1,10 @@
+use chess_state::*;
 use game::*;
-use repetition_detector::RepetitionDetector;
-use shakmaty::*;
+use game_state::*;
+use move_selection::*;
 use std::f32;
 use std::i16;
+use std::mem;
 use std::ops::Not;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -14,98 +16,210 @@ pub enum NodeState {
     LeafNode,
 }
 
+// a result the search has proven, for the player to move in the node
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Proof {
+    Win,
+    Loss,
+    Draw,
+}
+
+// how far the secure child distrusts the minimax of a move visited once, in centipawns
+const SECURE_CHILD_A: f32 = 100.;
+
 impl Default for NodeState {
     fn default() -> NodeState {
         NodeState::Expandable
     }
 }
 
-#[derive(Debug)]
-pub struct TreeNode {
-    pub action: Option<Move>, // how did we get here
+// the engine searches chess, in whichever variant is being played, but the tree works for any game
+#[derive(Debug, Clone)]
+pub struct TreeNode<S: GameState = ChessState> {
+    pub action: Option<S::Action>, // how did we get here
     pub value: Reward,
     pub minimax: Reward,
+    // score bounded MCTS: the worst and best the first player can still get from here
+    pub pessimistic: Reward,
+    pub optimistic: Reward,
     pub state: NodeState,
-    pub position: Chess,
-    pub repetition_detector: RepetitionDetector,
+    pub prior: f32, // PUCT's share of the parent's exploration, set when the node is created
+    pub game: S,
     pub n: u32,
     pub q: f32,
-    pub children: Vec<TreeNode>,
+    // all moves as first: simulations where this node's action was played later on instead
+    pub amaf_n: u32,
+    pub amaf_q: f32,
+    // whether a value below here came from how the game got there, e.g. a repetition, so the
+    // node's statistics mustn't be shared with its transpositions
+    pub history_dependent: bool,
+    pub children: Vec<TreeNode<S>>,
+    // the actions without a child yet, with their priors, the most promising last. They're
+    // ordered the first time the node's expanded, since most nodes never are
+    pub unexpanded: Option<Vec<(S::Action, f32)>>,
 }
 
-impl Default for TreeNode {
-    fn default() -> TreeNode {
+impl<S: GameState> Default for TreeNode<S> {
+    fn default() -> TreeNode<S> {
         TreeNode {
             action: None,
             value: 0,
             minimax: 0,
+            pessimistic: MIN_REWARD,
+            optimistic: MAX_REWARD,
             state: NodeState::Expandable,
-            position: Default::default(),
-            repetition_detector: RepetitionDetector::default(),
+            prior: 0.,
+            game: Default::default(),
             n: 0,
             q: 0.0,
+            amaf_n: 0,
+            amaf_q: 0.0,
+            history_dependent: false,
             children: vec![],
+            unexpanded: None,
         }
     }
 }
 
 //TODO, make all contructors take a game, and never allow manual setting of value
-impl TreeNode {
-    pub fn new_empty_child(action: Move, parent: &TreeNode) -> TreeNode {
-        let mut position = parent.position.clone();
-        position.make_move(&action);
+impl<S: GameState> TreeNode<S> {
+    pub fn new_empty_child(action: S::Action, parent: &TreeNode<S>) -> TreeNode<S> {
+        let mut game = parent.game.clone();
+        game.apply(&action);
         TreeNode {
             action: Some(action),
-            position: position.clone(),
-            repetition_detector: parent.repetition_detector.clone_and_record(&position),
+            game,
             state: NodeState::Empty, // we're about to expand it in iteration()
             ..Default::default()
         }
     }
 
-    pub fn new_root(position: Chess) -> TreeNode {
+    pub fn new_root(game: S) -> TreeNode<S> {
         TreeNode {
-            position: position.clone(),
-            repetition_detector: RepetitionDetector::new(&position),
+            game,
             state: NodeState::Expandable, // don't want empty, because don't want to run a playout on it
             ..Default::default()
         }
     }
 
-    pub fn clone_childless(&self) -> TreeNode {
+    pub fn clone_childless(&self) -> TreeNode<S> {
         TreeNode {
-            position: self.position.clone(),
+            game: self.game.clone(),
             children: Vec::new(),
-            repetition_detector: self.repetition_detector.clone(),
+            unexpanded: None,
             n: self.n,
             q: self.q,
+            amaf_n: self.amaf_n,
+            amaf_q: self.amaf_q,
+            history_dependent: self.history_dependent,
             minimax: self.minimax,
+            pessimistic: self.pessimistic,
+            optimistic: self.optimistic,
             value: self.value,
             state: self.state,
+            prior: self.prior,
             action: self.action.clone(),
         }
     }
 
+    // an iteration still running through the node counts as a loss for the player who moved
+    // here, so other threads choose elsewhere until it's done
+    pub fn add_virtual_loss(&mut self) {
+        self.n += 1;
+        self.q -= self.player_to_move().not().coefficient() as f32;
+    }
+
+    pub fn remove_virtual_loss(&mut self) {
+        self.n -= 1;
+        self.q += self.player_to_move().not().coefficient() as f32;
+    }
+
     pub fn color_relative_minimax(&self) -> i16 {
-        self.minimax * self.turn().not().coefficient() as i16
+        self.minimax * self.player_to_move().not().coefficient() as i16
     }
 
     pub fn color_relative_q(&self) -> f32 {
-        self.q * self.turn().not().coefficient() as f32
+        self.q * self.player_to_move().not().coefficient() as f32
+    }
+
+    pub fn color_relative_amaf_q(&self) -> f32 {
+        self.amaf_q * self.player_to_move().not().coefficient() as f32
     }
 
     pub fn color_relative_reward(&self) -> Reward {
         // could save this calc, but don't think it's called much
-        self.position.color_relative_reward()
+        self.player_to_move().not().coefficient() * self.game.heuristic_value()
     }
 
-    pub fn best_child_sort_use_minimax(&self) -> bool {
-        // captures fully searched nodes which will have low ns
-        // so we can choose draws if position is losing and wins if position
-        // is winning. Also, ensures that we always choose decisive winning moves
-        // using shortest path
-        // self.is_decisive() || !self.is_searchable()
-        true
+    // the child we'd play once the search is over. A proven win is played right away. Otherwise,
+    // once some moves are proven, the secure child weighs their exact values against the
+    // estimates of the rest, and until then the chain of final move selections decides
+    pub fn best_child(&self, chain: &[FinalMoveSelection]) -> Option<&TreeNode<S>> {
+        if let Some(child) = self.winning_child() {
+            return Some(child);
+        }
+        if self.children.iter().any(|c| c.proof().is_some()) {
+            return select_final_move(&self.children, &[FinalMoveSelection::SecureChild]);
+        }
+        select_final_move(&self.children, chain)
+    }
+
+    // the best of the children the opponent is proven to lose
+    pub fn winning_child(&self) -> Option<&TreeNode<S>> {
+        self.children
+            .iter()
+            .filter(|c| c.proof() == Some(Proof::Loss))
+            .max_by_key(|c| c.color_relative_minimax())
+    }
+
+    // decided once the bounds say so. A win can be proven before we know how quickly it comes
+    pub fn proof(&self) -> Option<Proof> {
+        let (worst, best) = self.bounds_for(self.player_to_move());
+        if worst > 0 {
+            Some(Proof::Win)
+        } else if best < 0 {
+            Some(Proof::Loss)
+        } else if worst == best {
+            Some(Proof::Draw)
+        } else {
+            None
+        }
+    }
+
+    // the pessimistic and optimistic bounds from a player's point of view
+    pub fn bounds_for(&self, player: Player) -> (Reward, Reward) {
+        match player {
+            Player::First => (self.pessimistic, self.optimistic),
+            Player::Second => (-self.optimistic, -self.pessimistic),
+        }
+    }
+
+    // the most the player to move is sure of getting from the children searched so far
+    pub fn secured_by_children(&self) -> Reward {
+        let player = self.player_to_move();
+        self.children
+            .iter()
+            .map(|c| c.bounds_for(player).0)
+            .max()
+            .unwrap_or(MIN_REWARD)
+    }
+
+    // a move that can't get the player making it what another move already secures isn't worth
+    // searching
+    pub fn is_pruned(&self, secured: Reward) -> bool {
+        self.bounds_for(!self.player_to_move()).1 < secured
+    }
+
+    // the lower confidence bound of the minimax for the player who moved here
+    pub fn secure_value(&self) -> f32 {
+        let minimax = self.color_relative_minimax() as f32;
+        if self.proof().is_some() {
+            minimax
+        } else if self.n == 0 {
+            f32::MIN
+        } else {
+            minimax - SECURE_CHILD_A / (self.n as f32).sqrt()
+        }
     }
 
     pub fn best_child_sort_minimax(&self) -> Reward {
@@ -114,55 +228,39 @@ impl TreeNode {
                 // shouldn't except very fast time controls.
                 // ensure we only choose this if all are Empty, then pick highest board value
                 error!("choosing from unexpanded node");
-                self.turn().not().coefficient() * -5000 + self.color_relative_reward()
+                self.player_to_move().not().coefficient() * -5000 + self.color_relative_reward()
             }
             _ => self.color_relative_minimax(),
         }
     }
 
-    pub fn best_child_sort_n(&self) -> f32 {
-        self.n as f32 + self.turn().not().coefficient() as f32 * self.q
-    }
-
-    pub fn is_checkmate(&self) -> bool {
-        self.position.is_checkmate()
-    }
-
-    // checkmate discovered for either color
+    // a forced win discovered for either player, e.g. a checkmate
     pub fn is_decisive(&self) -> bool {
-        self.color_relative_minimax() > MAX_REWARD - 100
+        self.color_relative_minimax() > MAX_REWARD - MAX_MATE_PLIES
     }
 
     pub fn is_searchable(&self) -> bool {
         ![NodeState::LeafNode, NodeState::FullySearched].contains(&self.state)
     }
 
-    pub fn display_move_num(&self) -> String {
-        self.position.display_move_num()
+    pub fn count_nodes(&self) -> u64 {
+        1 + self.children.iter().map(|c| c.count_nodes()).sum::<u64>()
     }
 
-    pub fn turn(&self) -> Color {
-        self.position.turn()
+    pub fn estimated_size(&self) -> usize {
+        mem::size_of::<TreeNode<S>>() - mem::size_of::<S>() + self.game.estimated_size()
     }
 
-    pub fn is_game_over(&self) -> bool {
-        self.position.is_game_over()
-            || self.position.halfmoves() == MAX_HALFMOVES
-            || self.repetition_detector.is_drawn(&self.position)
+    // with the moves it has left to expand, once it's been expanded, at most as many as it has
+    pub fn estimated_expanded_size(&self) -> usize {
+        self.estimated_size() + self.game.legal_actions().len() * mem::size_of::<(S::Action, f32)>()
     }
 
-    pub fn is_drawn(&self) -> bool {
-        self.position.halfmoves() == MAX_HALFMOVES
-            || self.repetition_detector.is_drawn(&self.position)
-            || self.position.is_stalemate()
-            || self.position.is_insufficient_material()
+    pub fn player_to_move(&self) -> Player {
+        self.game.player_to_move()
     }
 
-    pub fn outcome(&self) -> Option<Outcome> {
-        if self.is_drawn() {
-            return Some(Outcome::Draw);
-        } else {
-            return self.position.outcome();
-        }
+    pub fn is_game_over(&self) -> bool {
+        self.game.is_terminal()
     }
 }
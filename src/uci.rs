@@ -0,0 +1,407 @@
+use engine::*;
+use error::*;
+use log::*;
+use options::*;
+use search_strategy::*;
+use settings::*;
+use setup::*;
+use shakmaty::{Color, Setup};
+use std::process;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use time_control::*;
+use utils::*;
+
+#[derive(Debug, Default)]
+pub struct Uci {
+    // the position command the engine's state currently reflects, so we can apply only the new
+    // moves and keep the search tree
+    fen: Option<String>,
+    moves: Vec<String>,
+    thinking: bool, // searching in the background until a limit from go, then sending bestmove
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GoParams {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<u64>,
+    pub nodes: Option<u32>,
+    pub infinite: bool,
+}
+
+impl Uci {
+    pub fn start(&mut self, engine: &mut Engine) {
+        let commands = spawn_stdin_reader();
+
+        loop {
+            match commands.recv_timeout(POLL_INTERVAL) {
+                Ok(input) => self.handle_command(engine, input.trim()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    engine.stop_background_search();
+                    return;
+                }
+            }
+            self.poll(engine);
+        }
+    }
+
+    // sends our move once the search for it has reached the limits go gave it
+    pub fn poll(&mut self, engine: &mut Engine) {
+        if self.thinking && engine.is_background_search_finished() {
+            self.thinking = false;
+            engine.stop_background_search();
+            self.send_best_move(engine);
+        }
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    // runs the command and tells the GUI if it failed
+    pub fn handle_command(&mut self, engine: &mut Engine, cmd: &str) {
+        if let Err(error) = self.run_command(engine, cmd) {
+            error!("{}", error);
+            send(&error_message(&error, cmd));
+        }
+    }
+
+    pub fn run_command(&mut self, engine: &mut Engine, cmd: &str) -> Result<(), EngineError> {
+        warn!("RECEIVED: {}", cmd);
+        let tokens: Vec<&str> = cmd.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"quit") => {
+                engine.stop_background_search();
+                process::exit(0)
+            }
+            Some(&"uci") => {
+                engine.set_protocol(Protocol::Uci);
+                send(&format!("id name {}", ENGINE_NAME));
+                send("id author Brad Urani");
+                for option in engine_options(&engine.settings) {
+                    send(&option.to_uci_option());
+                }
+                send("uciok");
+            }
+            Some(&"isready") => send("readyok"),
+            Some(&"ucinewgame") => {
+                self.thinking = false;
+                engine.stop_background_search();
+                engine.reset();
+                self.fen = None;
+                self.moves = vec![];
+            }
+            Some(&"position") => {
+                self.thinking = false;
+                engine.stop_background_search();
+                self.set_position(engine, &tokens[1..])?;
+            }
+            Some(&"go") => {
+                self.thinking = false;
+                engine.stop_background_search();
+                let params = parse_go_params(&tokens[1..])?;
+                self.go(engine, &params)?;
+            }
+            Some(&"stop") => {
+                self.thinking = false;
+                if engine.stop_background_search().is_some() {
+                    self.send_best_move(engine);
+                }
+            }
+            Some(&"setoption") => {
+                let (name, value) = parse_setoption(&tokens[1..])?;
+                engine.set_option(&name, &value)?;
+            }
+            Some(&"debug") | Some(&"register") | Some(&"ponderhit") => {}
+            _ => return Err(EngineError::UnknownCommand),
+        };
+        Ok(())
+    }
+
+    fn set_position(&mut self, engine: &mut Engine, args: &[&str]) -> Result<(), EngineError> {
+        let moves_index = args.iter().position(|a| *a == "moves");
+        let (position_args, move_args) = match moves_index {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &args[args.len()..]),
+        };
+        let fen = match position_args.split_first() {
+            Some((&"startpos", _)) => None,
+            Some((&"fen", fen_args)) => Some(fen_args.join(" ")),
+            _ => return Err(EngineError::Parse("expected startpos or fen".to_string())),
+        };
+        let moves: Vec<String> = move_args.iter().map(|m| m.to_string()).collect();
+
+        let continues_game = fen == self.fen
+            && moves.len() >= self.moves.len()
+            && moves[..self.moves.len()] == self.moves[..];
+        let new_moves = if continues_game {
+            moves[self.moves.len()..].to_vec()
+        } else {
+            match fen {
+                Some(ref f) => engine.set_board(f)?,
+                None => engine.reset(),
+            }
+            self.moves = vec![];
+            moves
+        };
+        self.fen = fen;
+        // only the moves played, so after an illegal one the next position picks up from there
+        for m in new_moves.into_iter() {
+            engine.make_user_move(&m)?;
+            self.moves.push(m);
+        }
+        Ok(())
+    }
+
+    // an infinite search only ends with stop, while the others end by themselves too
+    fn go(&mut self, engine: &mut Engine, params: &GoParams) -> Result<(), EngineError> {
+        if params.infinite {
+            return engine.start_background_search();
+        }
+        let search_type = search_type(params, engine.position().turn())
+            .unwrap_or_else(|| engine.settings.search_type.clone());
+        engine.start_thinking_with(search_type)?;
+        self.thinking = true;
+        Ok(())
+    }
+
+    fn send_best_move(&mut self, engine: &mut Engine) {
+        let best_move = engine.play_best_move();
+        let uci = to_uci(
+            &engine.previous_position,
+            &best_move,
+            engine.settings.chess960,
+        )
+        .to_string();
+        self.moves.push(uci.clone());
+        send(&format!("bestmove {}", uci));
+    }
+}
+
+pub fn parse_go_params(args: &[&str]) -> Result<GoParams, EngineError> {
+    fn value<T: ::std::str::FromStr>(name: &str, arg: Option<&&str>) -> Result<T, EngineError> {
+        arg.ok_or_else(|| EngineError::Parse(format!("missing value for {}", name)))?
+            .parse::<T>()
+            .map_err(|_| EngineError::Parse(format!("invalid value for {}", name)))
+    }
+
+    let mut params: GoParams = Default::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match *arg {
+            "wtime" => params.wtime = Some(value(arg, iter.next())?),
+            "btime" => params.btime = Some(value(arg, iter.next())?),
+            "winc" => params.winc = Some(value(arg, iter.next())?),
+            "binc" => params.binc = Some(value(arg, iter.next())?),
+            "movestogo" => params.movestogo = Some(value(arg, iter.next())?),
+            "movetime" => params.movetime = Some(value(arg, iter.next())?),
+            "nodes" => params.nodes = Some(value(arg, iter.next())?),
+            "infinite" => params.infinite = true,
+            _ => warn!("ignoring go argument {}", arg),
+        }
+    }
+    Ok(params)
+}
+
+// setoption name NAME [value VALUE], where both can contain spaces
+pub fn parse_setoption(args: &[&str]) -> Result<(String, String), EngineError> {
+    if args.first() != Some(&"name") {
+        return Err(EngineError::Parse("expected setoption name".to_string()));
+    }
+    let value_index = args.iter().position(|a| *a == "value");
+    let (name_args, value_args) = match value_index {
+        Some(i) => (&args[1..i], &args[i + 1..]),
+        None => (&args[1..], &args[args.len()..]),
+    };
+    if name_args.is_empty() {
+        return Err(EngineError::Parse("missing option name".to_string()));
+    }
+    Ok((name_args.join(" "), value_args.join(" ")))
+}
+
+// None means the GUI didn't limit the search, so we fall back to Settings
+fn search_type(params: &GoParams, turn: Color) -> Option<SearchType> {
+    if let Some(movetime) = params.movetime {
+        return Some(SearchType::Time(Duration::from_millis(movetime)));
+    }
+    if let Some(nodes) = params.nodes {
+        return Some(SearchType::Iterations(nodes));
+    }
+    let (time, inc) = match turn {
+        Color::White => (params.wtime, params.winc),
+        Color::Black => (params.btime, params.binc),
+    };
+    time.map(|time_ms| {
+        let remaining = Duration::from_millis(time_ms);
+        let increment = Duration::from_millis(inc.unwrap_or(0));
+        let time_control = match params.movestogo {
+            Some(moves_to_go) if moves_to_go > 0 => TimeControl::Conventional {
+                moves_per_session: moves_to_go,
+                base: remaining,
+                increment,
+            },
+            _ => TimeControl::Incremental {
+                base: remaining,
+                increment,
+            },
+        };
+        SearchType::Time(time_control.time_for_move(Some(remaining), 0))
+    })
+}
+
+// UCI has no error replies, so we explain in an info string
+pub fn error_message(error: &EngineError, cmd: &str) -> String {
+    match *error {
+        EngineError::UnknownCommand => format!("info string unknown command {}", cmd),
+        ref error => format!("info string {}", error),
+    }
+}
+
+fn send(msg: &str) {
+    println!("{}", msg);
+    warn!("SENDING: {}", msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn parses_go_params() {
+        let params = parse_go_params(&["wtime", "60000", "btime", "50000", "winc", "1000"]);
+        assert_eq!(
+            params,
+            Ok(GoParams {
+                wtime: Some(60000),
+                btime: Some(50000),
+                winc: Some(1000),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_go_params() {
+        assert!(parse_go_params(&["movetime", "fast"]).is_err());
+        assert!(parse_go_params(&["nodes"]).is_err());
+    }
+
+    #[test]
+    fn allocates_time_for_side_to_move() {
+        let params = parse_go_params(&["wtime", "60000", "btime", "3000", "movestogo", "10"]);
+        let params = params.unwrap();
+        match search_type(&params, Color::White) {
+            Some(SearchType::Time(d)) => assert_eq!(d, Duration::from_millis(5995)),
+            other => panic!("unexpected search type {:?}", other),
+        }
+        match search_type(&params, Color::Black) {
+            Some(SearchType::Time(d)) => assert_eq!(d, Duration::from_millis(295)),
+            other => panic!("unexpected search type {:?}", other),
+        }
+    }
+
+    #[test]
+    fn counts_go_nodes_in_iterations_whatever_the_threads() {
+        let params = parse_go_params(&["nodes", "20"]).unwrap();
+        let search_type = search_type(&params, Color::White).unwrap();
+        let mut engine = Engine::new(Settings {
+            max_threads: 4,
+            show_thinking: false,
+            ..Default::default()
+        });
+        engine.reset();
+        engine.search(&search_type).unwrap();
+        assert_eq!(engine.state.root.n, 20);
+    }
+
+    #[test]
+    fn parses_setoption() {
+        assert_eq!(
+            parse_setoption(&["name", "Playout", "Depth", "value", "3"]),
+            Ok(("Playout Depth".to_string(), "3".to_string()))
+        );
+        assert!(parse_setoption(&["value", "3"]).is_err());
+        let mut uci: Uci = Default::default();
+        let mut engine: Engine = Default::default();
+        uci.run_command(&mut engine, "setoption name Seed value 7")
+            .unwrap();
+        assert_eq!(engine.settings.starting_seed, 7);
+        assert!(uci
+            .run_command(&mut engine, "setoption name Seed value 300")
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_tree_when_position_continues_game() {
+        let mut uci: Uci = Default::default();
+        let mut engine: Engine = Default::default();
+        engine.reset();
+        uci.run_command(&mut engine, "position startpos moves e2e4")
+            .unwrap();
+        uci.run_command(&mut engine, "position startpos moves e2e4 e7e5")
+            .unwrap();
+        assert_eq!(uci.moves, vec!["e2e4", "e7e5"]);
+        assert_eq!(engine.position().fullmoves(), 2);
+        uci.run_command(&mut engine, "position startpos moves d2d4")
+            .unwrap();
+        assert_eq!(uci.moves, vec!["d2d4"]);
+        assert_eq!(engine.position().fullmoves(), 1);
+    }
+
+    #[test]
+    fn keeps_the_moves_played_before_an_illegal_one() {
+        let mut uci: Uci = Default::default();
+        let mut engine: Engine = Default::default();
+        engine.reset();
+        assert!(uci
+            .run_command(&mut engine, "position startpos moves e2e4 e7e5 e1e3")
+            .is_err());
+        assert_eq!(uci.moves, vec!["e2e4", "e7e5"]);
+        uci.run_command(&mut engine, "position startpos moves e2e4 e7e5 g1f3")
+            .unwrap();
+        assert_eq!(uci.moves, vec!["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(engine.history.len(), 3);
+    }
+
+    #[test]
+    fn answers_while_searching() {
+        let mut uci: Uci = Default::default();
+        let mut engine = Engine::new(Settings {
+            show_thinking: false,
+            ..Default::default()
+        });
+        engine.reset();
+        uci.run_command(&mut engine, "go movetime 60000").unwrap();
+        assert!(uci.is_thinking());
+        uci.run_command(&mut engine, "isready").unwrap();
+        assert!(engine.is_searching_in_background());
+        uci.run_command(&mut engine, "stop").unwrap();
+        assert!(!uci.is_thinking());
+        assert!(!engine.is_searching_in_background());
+        assert_eq!(uci.moves.len(), 1);
+    }
+
+    #[test]
+    fn sends_bestmove_once_the_search_ends() {
+        let mut uci: Uci = Default::default();
+        let mut engine = Engine::new(Settings {
+            show_thinking: false,
+            ..Default::default()
+        });
+        engine.reset();
+        uci.run_command(&mut engine, "go nodes 20").unwrap();
+        while uci.is_thinking() {
+            thread::sleep(POLL_INTERVAL);
+            uci.poll(&mut engine);
+        }
+        assert_eq!(uci.moves.len(), 1);
+        assert_eq!(engine.history.len(), 1);
+    }
+}
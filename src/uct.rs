@@ -1,30 +1,154 @@
+use game_state::*;
+use rand::rngs::SmallRng;
+use rand::Rng;
 use settings::*;
 use std::cmp::Ordering::*;
 use std::f32;
 use tree_node::*;
 
+// how a child's mean value is traded off against exploring it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionFormula {
+    Ucb1,
+    Puct, // AlphaZero's, exploring in proportion to each child's prior
+}
+
+pub const SELECTION_FORMULAS: [SelectionFormula; 2] =
+    [SelectionFormula::Ucb1, SelectionFormula::Puct];
+
+impl SelectionFormula {
+    pub fn name(self) -> &'static str {
+        match self {
+            SelectionFormula::Ucb1 => "ucb1",
+            SelectionFormula::Puct => "puct",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SelectionFormula> {
+        SELECTION_FORMULAS
+            .iter()
+            .cloned()
+            .find(|f| f.name() == name)
+    }
+}
+
+pub fn parse_selection_formula(name: &str) -> Result<SelectionFormula, String> {
+    SelectionFormula::from_name(name.trim())
+        .ok_or(format!("unknown selection formula {}", name.trim()))
+}
+
+// progressive widening: a node visited n times may have ceil(c * n^exponent) children, so the
+// unpromising moves of a wide node are only tried once it's been searched a while
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Widening {
+    pub c: f32,
+    pub exponent: f32,
+}
+
+pub const DEFAULT_WIDENING: Widening = Widening {
+    c: 2.,
+    exponent: 0.5,
+};
+
+impl Widening {
+    pub fn max_children(&self, n: u32) -> usize {
+        (self.c * (n as f32).powf(self.exponent)).ceil().max(1.) as usize
+    }
+}
+
+// RAVE: how much of a child's mean comes from its all-moves-as-first value, which is learnt
+// quickly but biased, rather than its own visits. Beta falls from 1 towards 0 as they add up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaveSchedule {
+    // beta = sqrt(k / (3n + k)), so the two count equally after k visits
+    Equivalence(f32),
+    // beta = amaf_n / (n + amaf_n + 4 b^2 n amaf_n), minimising the error for an AMAF bias of b
+    MinimumMse(f32),
+}
+
+impl RaveSchedule {
+    pub fn beta(self, n: u32, amaf_n: u32) -> f32 {
+        let (n, amaf_n) = (n as f32, amaf_n as f32);
+        match self {
+            RaveSchedule::Equivalence(k) => (k / (3. * n + k)).sqrt(),
+            RaveSchedule::MinimumMse(b) => amaf_n / (n + amaf_n + 4. * b * b * n * amaf_n),
+        }
+    }
+}
+
+pub fn rave_schedule_name(schedule: Option<RaveSchedule>) -> String {
+    match schedule {
+        None => "none".to_string(),
+        Some(RaveSchedule::Equivalence(k)) => format!("equivalence={}", k),
+        Some(RaveSchedule::MinimumMse(b)) => format!("mse={}", b),
+    }
+}
+
+// e.g. "equivalence=1000", "mse=0.1" or "none"
+pub fn parse_rave_schedule(schedule: &str) -> Result<Option<RaveSchedule>, String> {
+    let invalid = || format!("unknown RAVE schedule {}", schedule.trim());
+    let parts: Vec<&str> = schedule.trim().splitn(2, '=').collect();
+    let parameter = || {
+        parts
+            .get(1)
+            .and_then(|p| p.trim().parse::<f32>().ok())
+            .filter(|p| *p >= 0.)
+            .ok_or_else(invalid)
+    };
+    match parts[0].trim() {
+        "none" if parts.len() == 1 => Ok(None),
+        "equivalence" => Ok(Some(RaveSchedule::Equivalence(parameter()?))),
+        "mse" => Ok(Some(RaveSchedule::MinimumMse(parameter()?))),
+        _ => Err(invalid()),
+    }
+}
+
+// the child's mean value for the player moving to it, blended with its AMAF value under RAVE
+fn mean<S: GameState>(child: &TreeNode<S>, settings: &Settings) -> f32 {
+    let mean = child.color_relative_q() / child.n as f32;
+    match settings.rave {
+        Some(schedule) if child.amaf_n > 0 => {
+            let beta = schedule.beta(child.n, child.amaf_n);
+            let amaf_mean = child.color_relative_amaf_q() / child.amaf_n as f32;
+            (1. - beta) * mean + beta * amaf_mean
+        }
+        _ => mean,
+    }
+}
+
 // 1) exploration factor: https://www.wolframalpha.com/input/?i=chart+y%3Dsqrt(ln(1000)%2Fx)+x%3D1..1000
 // 2) exploitation factor:
 // 3) value factor: (none)
 
-pub fn weight(child: &TreeNode, parent_n: u32, settings: &Settings) -> f32 {
+pub fn weight<S: GameState>(child: &TreeNode<S>, parent_n: u32, settings: &Settings) -> f32 {
     if !child.is_searchable() {
         // for sorting by weight
         return f32::MIN;
     } else if child.state == NodeState::Empty {
-        // weight these above everything, sorted by board value
+        // weight these above everything, sorted by board value, or by prior under PUCT
         // ensure they get expanded first so all roots's children get expanded,
         // and if we run out of time, the best nodes are first
-        // TODO order these, mate, check etc
-        //TODO change to color relative reward
-        return child.color_relative_reward() as f32 + 5000.;
+        return match settings.selection_formula {
+            SelectionFormula::Ucb1 => child.color_relative_reward() as f32 + 5000.,
+            SelectionFormula::Puct => child.prior + 5000.,
+        };
+    }
+    let mean = mean(child, settings);
+    match settings.selection_formula {
+        SelectionFormula::Ucb1 => {
+            mean + settings.c * ((parent_n as f32).ln() / child.n as f32).sqrt()
+        }
+        SelectionFormula::Puct => {
+            mean + settings.c * child.prior * (parent_n as f32).sqrt() / (1 + child.n) as f32
+        }
     }
-    let weight = (child.color_relative_q() as f32 / child.n as f32)
-        + settings.c * ((parent_n as f32).ln() / child.n as f32).sqrt();
-    weight
 }
 
-pub fn sort_children_by_weight(children: &mut Vec<TreeNode>, parent_n: u32, settings: &Settings) {
+pub fn sort_children_by_weight<S: GameState>(
+    children: &mut [TreeNode<S>],
+    parent_n: u32,
+    settings: &Settings,
+) {
     if cfg!(debug_assertions) {
         if children.iter().all(|c| !c.is_searchable()) {
             panic!("found no best children \n");
@@ -38,19 +162,55 @@ pub fn sort_children_by_weight(children: &mut Vec<TreeNode>, parent_n: u32, sett
     });
 }
 
-pub fn most_interesting_child<'a>(
-    parent: &'a mut TreeNode,
+// children weighted the same go to the last of them, as they always have under plain UCT. Under
+// RAVE or PUCT, where the priors and AMAF values tie far more moves, they're chosen between at
+// random, so searches with different seeds grow different trees
+pub fn most_interesting_child<'a, S: GameState>(
+    parent: &'a mut TreeNode<S>,
+    settings: &Settings,
+    rng: &mut SmallRng,
+) -> &'a mut TreeNode<S> {
+    let index = most_interesting_index(parent, settings, rng, |_| false)
+        .expect("no is_searchable children");
+    &mut parent.children[index]
+}
+
+// the same, passing over the children the caller says are busy, e.g. being played out by
+// another thread
+pub fn most_interesting_index<S, F>(
+    parent: &TreeNode<S>,
     settings: &Settings,
-) -> &'a mut TreeNode {
+    rng: &mut SmallRng,
+    is_busy: F,
+) -> Option<usize>
+where
+    S: GameState,
+    F: Fn(usize) -> bool,
+{
     let parent_n = parent.n;
-    parent
-        .children
-        .iter_mut()
-        .filter(|c| c.is_searchable())
-        .max_by(|a, b| {
-            weight(a, parent_n, settings)
-                .partial_cmp(&weight(b, parent_n, settings))
-                .unwrap_or(Equal)
-        })
-        .expect("no is_searchable children")
+    let secured = parent.secured_by_children();
+    let random_ties =
+        settings.rave.is_some() || settings.selection_formula == SelectionFormula::Puct;
+    let mut best: Option<(usize, f32)> = None;
+    let mut ties = 0;
+    for (index, child) in parent.children.iter().enumerate() {
+        if !child.is_searchable() || child.is_pruned(secured) || is_busy(index) {
+            continue;
+        }
+        let weight = weight(child, parent_n, settings);
+        match best {
+            Some((_, best_weight)) if weight < best_weight => {}
+            Some((_, best_weight)) if weight == best_weight && random_ties => {
+                ties += 1;
+                if rng.gen_range(0, ties + 1) == 0 {
+                    best = Some((index, weight));
+                }
+            }
+            _ => {
+                ties = 0;
+                best = Some((index, weight));
+            }
+        }
+    }
+    best.map(|(index, _)| index)
 }
@@ -5,6 +5,10 @@ use std::collections::HashMap;
 use std::fs::*;
 use std::hash::{BuildHasherDefault, Hash};
 use std::io::prelude::*;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
 use twox_hash::XxHash;
 
 pub fn choose_random<'a, T, R: Rng>(rng: &mut R, vec: &'a Vec<T>) -> &'a T {
@@ -36,6 +40,31 @@ pub fn file_to_string(filename: &'static str) -> String {
     contents
 }
 
+// how often the protocol loops check whether a search in the background has finished while
+// waiting for commands
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// the GUI's commands, read on their own thread so a search can be interrupted. The channel is
+// closed when stdin is
+pub fn spawn_stdin_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        loop {
+            let mut input = String::new();
+            match stdin.lock().read_line(&mut input) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(input).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
 #[cfg(test)]
 mod tests {
     //     use test::Bencher;
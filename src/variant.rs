@@ -0,0 +1,316 @@
+use error::*;
+use shakmaty::fen::*;
+use shakmaty::variants::*;
+use shakmaty::*;
+
+// the shakmaty variants we can play. Crazyhouse's drops aren't supported
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    Atomic,
+    Giveaway, // antichess
+    KingOfTheHill,
+    ThreeCheck,
+    Horde,
+    RacingKings,
+}
+
+pub const VARIANTS: [Variant; 7] = [
+    Variant::Standard,
+    Variant::Atomic,
+    Variant::Giveaway,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Horde,
+    Variant::RacingKings,
+];
+
+impl Variant {
+    pub fn xboard_name(self) -> &'static str {
+        match self {
+            Variant::Standard => "normal",
+            Variant::Atomic => "atomic",
+            Variant::Giveaway => "giveaway",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Horde => "horde",
+            Variant::RacingKings => "racingkings",
+        }
+    }
+
+    // as lichess writes them in the Variant tag
+    pub fn pgn_name(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Atomic => "Atomic",
+            Variant::Giveaway => "Antichess",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+        }
+    }
+
+    pub fn from_xboard_name(name: &str) -> Option<Variant> {
+        VARIANTS.iter().cloned().find(|v| v.xboard_name() == name)
+    }
+
+    pub fn starting_position(self) -> VariantPosition {
+        match self {
+            Variant::Standard => VariantPosition::Standard(Default::default()),
+            Variant::Atomic => VariantPosition::Atomic(Default::default()),
+            Variant::Giveaway => VariantPosition::Giveaway(Default::default()),
+            Variant::KingOfTheHill => VariantPosition::KingOfTheHill(Default::default()),
+            Variant::ThreeCheck => VariantPosition::ThreeCheck(Default::default()),
+            Variant::Horde => VariantPosition::Horde(Default::default()),
+            Variant::RacingKings => VariantPosition::RacingKings(Default::default()),
+        }
+    }
+
+    pub fn position_from_setup<S: Setup>(
+        self,
+        setup: &S,
+    ) -> Result<VariantPosition, PositionError> {
+        Ok(match self {
+            Variant::Standard => VariantPosition::Standard(Chess::from_setup(setup)?),
+            Variant::Atomic => VariantPosition::Atomic(Atomic::from_setup(setup)?),
+            Variant::Giveaway => VariantPosition::Giveaway(Giveaway::from_setup(setup)?),
+            Variant::KingOfTheHill => {
+                VariantPosition::KingOfTheHill(KingOfTheHill::from_setup(setup)?)
+            }
+            Variant::ThreeCheck => VariantPosition::ThreeCheck(ThreeCheck::from_setup(setup)?),
+            Variant::Horde => VariantPosition::Horde(Horde::from_setup(setup)?),
+            Variant::RacingKings => VariantPosition::RacingKings(RacingKings::from_setup(setup)?),
+        })
+    }
+
+    pub fn position_from_fen(self, fen_str: &str) -> Result<VariantPosition, EngineError> {
+        let fen: Fen = fen_str
+            .parse()
+            .map_err(|e: ParseFenError| EngineError::InvalidPosition(e.to_string()))?;
+        self.position_from_setup(&fen)
+            .map_err(|e: PositionError| EngineError::InvalidPosition(e.to_string()))
+    }
+}
+
+// a position in whichever variant is being played, so the variant can be picked at runtime
+#[derive(Debug, Clone)]
+pub enum VariantPosition {
+    Standard(Chess),
+    Atomic(Atomic),
+    Giveaway(Giveaway),
+    KingOfTheHill(KingOfTheHill),
+    ThreeCheck(ThreeCheck),
+    Horde(Horde),
+    RacingKings(RacingKings),
+}
+
+impl Default for VariantPosition {
+    fn default() -> VariantPosition {
+        VariantPosition::Standard(Default::default())
+    }
+}
+
+impl From<Chess> for VariantPosition {
+    fn from(position: Chess) -> VariantPosition {
+        VariantPosition::Standard(position)
+    }
+}
+
+impl VariantPosition {
+    pub fn variant(&self) -> Variant {
+        match *self {
+            VariantPosition::Standard(_) => Variant::Standard,
+            VariantPosition::Atomic(_) => Variant::Atomic,
+            VariantPosition::Giveaway(_) => Variant::Giveaway,
+            VariantPosition::KingOfTheHill(_) => Variant::KingOfTheHill,
+            VariantPosition::ThreeCheck(_) => Variant::ThreeCheck,
+            VariantPosition::Horde(_) => Variant::Horde,
+            VariantPosition::RacingKings(_) => Variant::RacingKings,
+        }
+    }
+}
+
+// calls the same method on whichever position we hold. Variants override many of Position's
+// default methods, so every method is passed on, not just the required ones
+macro_rules! delegate {
+    ($position:expr, $p:ident => $call:expr) => {
+        match $position {
+            VariantPosition::Standard($p) => $call,
+            VariantPosition::Atomic($p) => $call,
+            VariantPosition::Giveaway($p) => $call,
+            VariantPosition::KingOfTheHill($p) => $call,
+            VariantPosition::ThreeCheck($p) => $call,
+            VariantPosition::Horde($p) => $call,
+            VariantPosition::RacingKings($p) => $call,
+        }
+    };
+}
+
+impl Setup for VariantPosition {
+    fn board(&self) -> &Board {
+        delegate!(self, p => p.board())
+    }
+
+    fn pockets(&self) -> Option<&Material> {
+        delegate!(self, p => p.pockets())
+    }
+
+    fn turn(&self) -> Color {
+        delegate!(self, p => p.turn())
+    }
+
+    fn castling_rights(&self) -> Bitboard {
+        delegate!(self, p => p.castling_rights())
+    }
+
+    fn ep_square(&self) -> Option<Square> {
+        delegate!(self, p => p.ep_square())
+    }
+
+    fn remaining_checks(&self) -> Option<&RemainingChecks> {
+        delegate!(self, p => p.remaining_checks())
+    }
+
+    fn halfmoves(&self) -> u32 {
+        delegate!(self, p => p.halfmoves())
+    }
+
+    fn fullmoves(&self) -> u32 {
+        delegate!(self, p => p.fullmoves())
+    }
+}
+
+impl Position for VariantPosition {
+    // without a variant to go on, a setup is standard chess. See Variant::position_from_setup
+    fn from_setup<S: Setup>(setup: &S) -> Result<VariantPosition, PositionError> {
+        Variant::Standard.position_from_setup(setup)
+    }
+
+    fn swap_turn(self) -> Result<VariantPosition, PositionError> {
+        let variant = self.variant();
+        delegate!(self, p => variant.position_from_setup(&p.swap_turn()?))
+    }
+
+    fn legal_moves(&self, moves: &mut MoveList) {
+        delegate!(self, p => p.legal_moves(moves))
+    }
+
+    fn san_candidates(&self, role: Role, to: Square, moves: &mut MoveList) {
+        delegate!(self, p => p.san_candidates(role, to, moves))
+    }
+
+    fn castling_moves(&self, side: CastlingSide, moves: &mut MoveList) {
+        delegate!(self, p => p.castling_moves(side, moves))
+    }
+
+    fn en_passant_moves(&self, moves: &mut MoveList) {
+        delegate!(self, p => p.en_passant_moves(moves))
+    }
+
+    fn capture_moves(&self, moves: &mut MoveList) {
+        delegate!(self, p => p.capture_moves(moves))
+    }
+
+    fn promotion_moves(&self, moves: &mut MoveList) {
+        delegate!(self, p => p.promotion_moves(moves))
+    }
+
+    fn is_legal(&self, m: &Move) -> bool {
+        delegate!(self, p => p.is_legal(m))
+    }
+
+    fn is_irreversible(&self, m: &Move) -> bool {
+        delegate!(self, p => p.is_irreversible(m))
+    }
+
+    fn king_attackers(&self, square: Square, attacker: Color, occupied: Bitboard) -> Bitboard {
+        delegate!(self, p => p.king_attackers(square, attacker, occupied))
+    }
+
+    fn castles(&self) -> &Castles {
+        delegate!(self, p => p.castles())
+    }
+
+    fn is_check(&self) -> bool {
+        delegate!(self, p => p.is_check())
+    }
+
+    fn checkers(&self) -> Bitboard {
+        delegate!(self, p => p.checkers())
+    }
+
+    fn is_variant_end(&self) -> bool {
+        delegate!(self, p => p.is_variant_end())
+    }
+
+    fn is_checkmate(&self) -> bool {
+        delegate!(self, p => p.is_checkmate())
+    }
+
+    fn is_stalemate(&self) -> bool {
+        delegate!(self, p => p.is_stalemate())
+    }
+
+    fn is_insufficient_material(&self) -> bool {
+        delegate!(self, p => p.is_insufficient_material())
+    }
+
+    fn has_insufficient_material(&self, color: Color) -> bool {
+        delegate!(self, p => p.has_insufficient_material(color))
+    }
+
+    fn is_game_over(&self) -> bool {
+        delegate!(self, p => p.is_game_over())
+    }
+
+    fn variant_outcome(&self) -> Option<Outcome> {
+        delegate!(self, p => p.variant_outcome())
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        delegate!(self, p => p.outcome())
+    }
+
+    fn play_unchecked(&mut self, m: &Move) {
+        delegate!(self, p => p.play_unchecked(m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_variants_for_xboard() {
+        for variant in VARIANTS.iter() {
+            assert_eq!(
+                Variant::from_xboard_name(variant.xboard_name()),
+                Some(*variant)
+            );
+        }
+        assert_eq!(Variant::from_xboard_name("crazyhouse"), None);
+    }
+
+    #[test]
+    fn sets_up_variant_positions() {
+        let horde = Variant::Horde.starting_position();
+        assert_eq!(horde.variant(), Variant::Horde);
+        assert_eq!(horde.board().white().count(), 36);
+        let hill = Variant::KingOfTheHill
+            .position_from_fen("4k3/8/8/4K3/8/8/8/8 b - - 0 1")
+            .unwrap();
+        assert!(hill.is_variant_end());
+        assert_eq!(
+            hill.outcome(),
+            Some(Outcome::Decisive {
+                winner: Color::White
+            })
+        );
+        assert!(Variant::Standard
+            .position_from_fen("4k3/8/8/4K3/8/8/8/8 b - - 0 1")
+            .unwrap()
+            .is_insufficient_material());
+    }
+}
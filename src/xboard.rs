@@ -1,110 +1,274 @@
+use adjudication::*;
 use engine::*;
+use error::*;
+use game::*;
 use log::*;
+use options::*;
+use pgn::*;
 use shakmaty::uci::Uci;
 use shakmaty::Color::*;
-use std::io::{self, BufRead};
+use shakmaty::{CastlingSide, Move, Position, Setup};
+use stats::*;
 use std::process;
+use std::str::FromStr;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Instant;
+use time_control::*;
+use utils::*;
+use variant::*;
+
+// commands that don't need the position, so they don't interrupt a search in the background
+const BACKGROUND_COMMANDS: [&str; 18] = [
+    "?", "time", "otim", "ping", "post", "nopost", "hard", "easy", "accepted", "level", "st", "sd",
+    "name", "rating", "computer", "memory", "cores", "draw",
+];
 
 #[derive(Debug)]
 pub struct XBoard {
     force: bool,
+    analysis: Option<Analysis>,
+    thinking: bool,             // searching for our own move in the background
+    pending_pings: Vec<String>, // answered after we move, as CECP requires
+    game_info: GameInfo,        // for the PGN saved when the game ends
+    adjudication: Adjudication,
+}
+
+// analyze mode searches in the background until the GUI sends exit
+#[derive(Debug)]
+struct Analysis {
+    start_time: Instant,
+    stats: RunStats, // of the searches already stopped for board edits and status requests
 }
 
 impl Default for XBoard {
     fn default() -> XBoard {
-        XBoard { force: false }
+        XBoard {
+            force: false,
+            analysis: None,
+            thinking: false,
+            pending_pings: vec![],
+            game_info: Default::default(),
+            adjudication: Default::default(),
+        }
     }
 }
 
 impl XBoard {
     pub fn start(&mut self, engine: &mut Engine) {
-        let stdin = io::stdin();
+        let commands = spawn_stdin_reader();
 
         loop {
-            let mut input = String::new();
-            stdin.lock().read_line(&mut input).unwrap();
-            let result = self.run_command(engine, &input.trim_right()[..]);
-            match result {
-                Ok(_) => {}
-                Err(msg) => error!("{}", msg),
+            match commands.recv_timeout(POLL_INTERVAL) {
+                Ok(input) => self.handle_command(engine, input.trim_end()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    engine.stop_background_search();
+                    return;
+                }
             }
+            if let Err(error) = self.poll(engine) {
+                error!("{}", error);
+            }
+        }
+    }
+
+    // runs the command and tells the GUI if it failed
+    pub fn handle_command(&mut self, engine: &mut Engine, cmd: &str) {
+        if let Err(error) = self.run_command(engine, cmd) {
+            error!("{}", error);
+            send(&error_message(&error, cmd));
         }
     }
 
-    pub fn run_command(&mut self, engine: &mut Engine, cmd: &str) -> Result<(), String> {
+    // plays our move once the search for it has run out of time or iterations
+    pub fn poll(&mut self, engine: &mut Engine) -> Result<(), EngineError> {
+        if self.thinking && engine.is_background_search_finished() {
+            self.finish_thinking(engine)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    // the pings that get their pongs once we've moved
+    pub fn pending_pings(&self) -> &[String] {
+        &self.pending_pings
+    }
+
+    pub fn run_command(&mut self, engine: &mut Engine, cmd: &str) -> Result<(), EngineError> {
         warn!("RECEIVED: {}", cmd);
 
+        if self.analysis.is_some() {
+            return self.run_analysis_command(engine, cmd);
+        }
+
+        let keyword = cmd.split(' ').next().unwrap_or("");
+        let interrupts_search = match keyword {
+            // making the user's move stops pondering itself, so it can count ponder hits
+            "usermove" => self.thinking,
+            _ => !BACKGROUND_COMMANDS.contains(&keyword),
+        };
+        if interrupts_search {
+            // e.g. force or result while thinking: stop without moving
+            engine.stop_background_search();
+            self.thinking = false;
+        }
+
         if cmd == "quit" {
             process::exit(0)
         } else if cmd == "protover 2" {
             send("feature done=0");
-            send("feature myname=\"deadbeef\"");
+            send(&format!("feature myname=\"{}\"", ENGINE_NAME));
             send("feature usermove=1");
             send("feature setboard=1");
             send("feature ping=1");
+            send("feature name=1");
+            send("feature memory=1");
+            send("feature smp=1");
+            send("feature analyze=1");
             send("feature sigint=0");
-            send("feature variants=\"normal\"");
+            send(&format!("feature variants=\"{}\"", xboard_variants()));
+            for option in engine_options(&engine.settings)
+                .iter()
+                .filter(|o| o.name != CHESS960)
+            {
+                send(&format!("feature option=\"{}\"", option.to_xboard_feature()));
+            }
             send("feature done=1");
         } else if cmd == "new" {
+            self.force = false;
+            self.adjudication = Default::default();
+            // sd only limits the game it was sent in
+            engine.settings.max_depth = None;
+            // a variant command follows new for other variants
+            engine.settings.chess960 = false;
+            engine.set_variant(Variant::Standard);
+            engine.reset();
+        } else if cmd.starts_with("variant") {
+            let name = argument(cmd, "variant")?;
+            let variant = match name {
+                "fischerandom" => Some(Variant::Standard),
+                _ => Variant::from_xboard_name(name),
+            }
+            .ok_or_else(|| EngineError::Parse(format!("unsupported variant {}", name)))?;
+            engine.settings.chess960 = name == "fischerandom";
+            engine.set_variant(variant);
             engine.reset();
         } else if cmd.starts_with("setboard") {
-            let fen: &str = cmd
-                .splitn(2, ' ')
-                .collect::<Vec<&str>>()
-                .get(1)
-                .ok_or("no board string".to_string())?;
+            let fen = argument(cmd, "board")?;
             engine.set_board(fen)?;
+            self.adjudication = Default::default();
+        } else if cmd == "analyze" {
+            self.analysis = Some(Analysis {
+                start_time: Instant::now(),
+                stats: Default::default(),
+            });
+            resume_analysis(engine);
         } else if cmd == "force" {
             self.force = true;
         } else if cmd == "go" {
             self.force = false;
-            go(engine)?;
+            self.go(engine)?;
+        } else if cmd == "?" {
+            if self.thinking {
+                self.finish_thinking(engine)?;
+            }
         } else if cmd.starts_with("ping") {
-            let n: &str = cmd
-                .splitn(2, ' ')
-                .collect::<Vec<&str>>()
-                .get(1)
-                .ok_or("no ping num".to_string())?;
-            send(&format!("pong {}", n));
+            let n = argument(cmd, "ping number")?;
+            if self.thinking {
+                self.pending_pings.push(n.to_string());
+            } else {
+                send(&format!("pong {}", n));
+            }
         } else if cmd.starts_with("usermove") {
-            let action: &str = cmd
-                .splitn(2, ' ')
-                .collect::<Vec<&str>>()
-                .get(1)
-                .ok_or("no user move".to_string())?;
+            let action = argument(cmd, "move")?;
             engine.make_user_move(action)?;
-            if !engine.is_game_over() {
-                go(engine)?;
+            if !self.force {
+                match claim_result(&engine.state.root) {
+                    Some(result) => send(result),
+                    None => self.go(engine)?,
+                }
             }
+        } else if cmd == "undo" {
+            engine.undo()?;
+        } else if cmd == "remove" {
+            engine.undo_n(2)?;
         } else if cmd.starts_with("time") {
-            let time: &str = cmd
-                .splitn(2, ' ')
-                .collect::<Vec<&str>>()
-                .get(1)
-                .ok_or("missing time".to_string())?;
-            let time_cs = time.parse::<u64>().map_err(|e| e.to_string())?;
-            engine.set_time_remaining_cs(time_cs);
+            let time = argument(cmd, "time")?;
+            engine.set_time_remaining_cs(parse_number(time, "time")?);
         } else if cmd.starts_with("otim") {
-            let time: &str = cmd
-                .splitn(2, ' ')
-                .collect::<Vec<&str>>()
-                .get(1)
-                .ok_or("missing time".to_string())?;
-            let time_cs = time.parse::<u64>().map_err(|e| e.to_string())?;
-            engine.set_opponent_time_remaining_cs(time_cs);
+            let time = argument(cmd, "time")?;
+            engine.set_opponent_time_remaining_cs(parse_number(time, "time")?);
+        } else if cmd.starts_with("level") {
+            let args = argument(cmd, "level")?;
+            engine.set_time_control(TimeControl::parse_level(args).map_err(EngineError::Parse)?);
+        } else if cmd.starts_with("st ") {
+            let seconds = argument(cmd, "seconds")?;
+            let time_control = TimeControl::parse_fixed_time(seconds).map_err(EngineError::Parse)?;
+            engine.set_time_control(time_control);
+        } else if cmd.starts_with("sd ") {
+            let depth = argument(cmd, "depth")?;
+            engine.set_max_depth(parse_number(depth, "depth")?);
+        } else if cmd.starts_with("memory") {
+            let memory = argument(cmd, "memory")?;
+            engine.set_memory_mb(parse_number(memory, "memory")?);
+        } else if cmd.starts_with("cores") {
+            let cores = argument(cmd, "cores")?;
+            engine.set_max_threads(parse_number(cores, "cores")?);
         } else if cmd.starts_with("post") {
             engine.set_show_thinking(true);
         } else if cmd.starts_with("nopost") {
             engine.set_show_thinking(false);
+        } else if cmd == "hard" {
+            engine.set_ponder(true);
+        } else if cmd == "easy" {
+            engine.set_ponder(false);
+            if !self.thinking {
+                engine.stop_background_search();
+            }
+        } else if cmd.starts_with("result") {
+            self.force = true;
+            let args = argument(cmd, "result")?;
+            self.game_info
+                .set_result(args)
+                .map_err(EngineError::Parse)?;
+            let saved = engine.save_pgn(&self.game_info);
+            // the opponent's info is sent again before the next game
+            self.game_info = Default::default();
+            saved?;
+        } else if cmd.starts_with("name") {
+            let name = argument(cmd, "name")?;
+            self.game_info.opponent_name = Some(name.to_string());
+        } else if cmd.starts_with("rating") {
+            let args = argument(cmd, "ratings")?;
+            self.game_info
+                .set_ratings(args)
+                .map_err(EngineError::Parse)?;
+        } else if cmd.starts_with("option") {
+            let args = argument(cmd, "option")?;
+            let mut name_value = args.splitn(2, '=');
+            let name = name_value.next().unwrap_or("");
+            let value = name_value
+                .next()
+                .ok_or_else(|| EngineError::Parse(format!("missing value for option {}", name)))?;
+            engine.set_option(name, value)?;
+        } else if cmd == "draw" {
+            if self.adjudication.accepts_draw(&engine.settings) {
+                send("offer draw");
+            }
+        } else if cmd == "computer" {
+            self.game_info.opponent_is_computer = true;
         } else if cmd == "white" {
             engine.set_color(White);
         } else if cmd == "black" {
             engine.set_color(Black);
-        } else if vec!["xboard", "random", "hard", "accepted", "level"]
+        } else if vec!["xboard", "random", "accepted"]
             .iter()
             .any(|c| cmd.starts_with(c))
         {
-
+            // nothing to do
         }
         // 0xDEADBEEF extensions. Not part of xboard
         else if cmd == "search" {
@@ -114,17 +278,146 @@ impl XBoard {
             let uci_strs: Vec<&str> = args.into_iter().skip(1).collect();
             engine.print_subtree(uci_strs)?;
         } else {
-            return Err("unknown command".to_string());
+            return Err(EngineError::UnknownCommand);
+        };
+        Ok(())
+    }
+
+    // the search keeps running between commands, so it's paused for anything that needs the
+    // engine's state, and resumed on the new position
+    fn run_analysis_command(&mut self, engine: &mut Engine, cmd: &str) -> Result<(), EngineError> {
+        if let Some(stats) = engine.stop_background_search() {
+            if let Some(ref mut analysis) = self.analysis {
+                analysis.stats.add(&stats);
+            }
+        }
+        let result = if cmd == "exit" {
+            self.analysis = None;
+            return Ok(());
+        } else if cmd == "." {
+            self.send_analysis_status(engine);
+            Ok(())
+        } else if cmd.starts_with("usermove") {
+            let action = argument(cmd, "move")?;
+            engine.make_user_move(action).map(|_| ())
+        } else if cmd == "go" {
+            Err(EngineError::NotLegalNow("cannot go in analyze mode".to_string()))
+        } else {
+            let analysis = self.analysis.take();
+            let result = self.run_command(engine, cmd);
+            self.analysis = analysis;
+            result
         };
+        resume_analysis(engine);
+        result
+    }
+
+    fn go(&mut self, engine: &mut Engine) -> Result<(), EngineError> {
+        let turn = engine.position().turn();
+        engine.start_thinking()?;
+        self.game_info.our_color = Some(turn);
+        self.thinking = true;
+        Ok(())
+    }
+
+    // stops the search for our move, e.g. for ?, and plays the best move found so far
+    fn finish_thinking(&mut self, engine: &mut Engine) -> Result<(), EngineError> {
+        engine.stop_background_search();
+        self.thinking = false;
+        let position = engine.position();
+        let score = engine.minimax() * position.turn().coefficient();
+        let decision = self
+            .adjudication
+            .decide(score, position.fullmoves(), &engine.settings);
+        if decision == Decision::Resign {
+            send("resign");
+            self.force = true;
+        } else {
+            if decision == Decision::OfferDraw {
+                send("offer draw");
+            }
+            let best_move = engine.play_best_move();
+            send(&format!(
+                "move {}",
+                xboard_move(&engine.previous_position, &best_move, engine.settings.chess960)
+            ));
+            if let Some(result) = claim_result(&engine.state.root) {
+                send(result);
+            }
+        }
+        for n in self.pending_pings.drain(..) {
+            send(&format!("pong {}", n));
+        }
+        if engine.settings.ponder && !self.force && !engine.is_game_over() {
+            engine.start_pondering()?;
+        }
         Ok(())
     }
+
+    // stat01: TIME NODES PLY MVLEFT MVTOT
+    fn send_analysis_status(&self, engine: &Engine) {
+        if let Some(ref analysis) = self.analysis {
+            let root = &engine.state.root;
+            let total_moves = engine.position().legals().len();
+            let searched_moves = root.children.iter().filter(|c| c.n > 0).count();
+            send(&format!(
+                "stat01: {} {} {} {} {}",
+                analysis.start_time.elapsed().as_millis() / 10,
+                analysis.stats.evals,
+                analysis.stats.comprehensive_max_depth(),
+                total_moves - searched_moves,
+                total_moves
+            ));
+        }
+    }
+}
+
+fn resume_analysis(engine: &mut Engine) {
+    if !engine.is_game_over() {
+        if let Err(error) = engine.start_analysis() {
+            error!("{}", error);
+        }
+    }
+}
+
+// Chess960 is standard chess from a shuffled position, as far as the search is concerned
+fn xboard_variants() -> String {
+    let mut names: Vec<&str> = VARIANTS.iter().map(|v| v.xboard_name()).collect();
+    names.insert(1, "fischerandom");
+    names.join(",")
+}
+
+// xboard sends and expects Chess960 castles as O-O and O-O-O
+fn xboard_move<P: Position>(position: &P, action: &Move, chess960: bool) -> String {
+    match action.castling_side() {
+        Some(CastlingSide::KingSide) if chess960 => "O-O".to_string(),
+        Some(CastlingSide::QueenSide) if chess960 => "O-O-O".to_string(),
+        _ => Uci::from_move(position, action).to_string(),
+    }
 }
 
-fn go(engine: &mut Engine) -> Result<(), String> {
-    let best_move = engine.make_engine_move()?;
-    let uci = Uci::from_move(&engine.previous_position, &best_move);
-    send(&format!("move {}", uci.to_string()));
-    Ok(())
+// the rest of the command after its keyword, e.g. the FEN of setboard
+fn argument<'a>(cmd: &'a str, name: &str) -> Result<&'a str, EngineError> {
+    cmd.split_once(' ')
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| EngineError::Parse(format!("missing {}", name)))
+}
+
+fn parse_number<T: FromStr>(arg: &str, name: &str) -> Result<T, EngineError> {
+    arg.trim()
+        .parse::<T>()
+        .map_err(|_| EngineError::Parse(format!("invalid {} {}", name, arg)))
+}
+
+// CECP reports illegal moves with the move, bad positions to the user, and anything else as
+// Error (REASON): COMMAND
+pub fn error_message(error: &EngineError, cmd: &str) -> String {
+    match *error {
+        EngineError::IllegalMove(ref action) => format!("Illegal move: {}", action),
+        EngineError::InvalidPosition(_) => "tellusererror Illegal position".to_string(),
+        EngineError::UnknownCommand => format!("Error (unknown command): {}", cmd),
+        ref error => format!("Error ({}): {}", error, cmd),
+    }
 }
 
 fn send(msg: &str) {
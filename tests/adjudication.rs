@@ -0,0 +1,44 @@
+extern crate deadbeef;
+
+use deadbeef::settings::*;
+use deadbeef::xboard::*;
+use helpers::*;
+
+mod helpers;
+
+#[test]
+fn resigns_instead_of_moving() {
+    let mut engine = new_engine(Settings {
+        resign_score: Some(10000),
+        resign_moves: 1,
+        ..iteration_settings(20)
+    });
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    assert_eq!(engine.history.len(), 1);
+    // resigning leaves the engine in force mode
+    xboard.run_command(&mut engine, "usermove e7e5").unwrap();
+    assert!(!xboard.is_thinking());
+}
+
+#[test]
+fn does_not_think_after_user_mates() {
+    let mut engine = new_engine(iteration_settings(20));
+    let mut xboard: XBoard = Default::default();
+    xboard
+        .run_command(&mut engine, "setboard k7/8/1K6/8/8/8/8/7R w - - 0 1")
+        .unwrap();
+    xboard.run_command(&mut engine, "usermove h1h8").unwrap();
+    assert!(!xboard.is_thinking());
+    assert!(engine.is_game_over());
+}
+
+#[test]
+fn plays_on_when_scores_are_unremarkable() {
+    let mut engine = new_engine(iteration_settings(20));
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    assert_eq!(engine.history.len(), 2);
+}
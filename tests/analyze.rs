@@ -0,0 +1,61 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::settings::*;
+use deadbeef::xboard::*;
+use helpers::*;
+use shakmaty::fen::*;
+use std::thread;
+use std::time::Duration;
+
+mod helpers;
+
+#[test]
+fn analyzes_until_exit() {
+    let mut engine = new_engine(Settings::test_default());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "analyze").unwrap();
+    assert!(engine.is_searching_in_background());
+    thread::sleep(Duration::from_millis(200));
+    xboard.run_command(&mut engine, ".").unwrap();
+    assert!(engine.is_searching_in_background());
+    xboard.run_command(&mut engine, "exit").unwrap();
+    assert!(!engine.is_searching_in_background());
+    assert!(engine.state.root.n > 0);
+}
+
+#[test]
+fn reroots_analysis_on_board_edits() {
+    let mut engine = new_engine(Settings::test_default());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "analyze").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    assert!(engine.is_searching_in_background());
+    thread::sleep(Duration::from_millis(100));
+    xboard.run_command(&mut engine, "exit").unwrap();
+    assert_eq!(
+        fen(&engine.position()),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert!(engine.state.root.n > 0);
+
+    xboard.run_command(&mut engine, "analyze").unwrap();
+    xboard.run_command(&mut engine, "undo").unwrap();
+    xboard
+        .run_command(&mut engine, "setboard 4k3/8/4K3/8/8/8/8/7R w - - 0 1")
+        .unwrap();
+    xboard.run_command(&mut engine, "exit").unwrap();
+    assert_eq!(fen(&engine.position()), "4k3/8/4K3/8/8/8/8/7R w - - 0 1");
+}
+
+#[test]
+fn does_not_analyze_finished_game() {
+    let mut engine = new_engine(Settings::test_default());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "analyze").unwrap();
+    xboard
+        .run_command(&mut engine, "setboard 4k3/4Q3/4K3/8/8/8/8/8 b - - 0 1")
+        .unwrap();
+    assert!(!engine.is_searching_in_background());
+    xboard.run_command(&mut engine, "exit").unwrap();
+}
@@ -0,0 +1,85 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::engine::*;
+use deadbeef::error::*;
+use deadbeef::setup::*;
+use deadbeef::uci::*;
+use deadbeef::xboard::*;
+use helpers::*;
+use shakmaty::fen::*;
+
+mod helpers;
+
+fn board_fen(engine: &Engine) -> String {
+    fen(&engine.position())
+        .split(' ')
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn uci_plays_shredder_fen_castles() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut uci: Uci = Default::default();
+    uci.run_command(&mut engine, "setoption name UCI_Chess960 value true")
+        .unwrap();
+    assert!(engine.settings.chess960);
+    uci.run_command(
+        &mut engine,
+        "position fen 1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1 moves e1b1 e8g8",
+    )
+    .unwrap();
+    assert_eq!(
+        board_fen(&engine),
+        "1r3rk1/pppppppp/8/8/8/8/PPPPPPPP/2KR2R1"
+    );
+}
+
+#[test]
+fn xboard_plays_x_fen_castles() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut xboard: XBoard = Default::default();
+    for cmd in &[
+        "new",
+        "variant fischerandom",
+        "force",
+        "setboard r5kr/pppppppp/8/8/8/8/PPPPPPPP/R5KR w KQkq - 0 1",
+        "usermove O-O",
+        "usermove O-O-O",
+    ] {
+        xboard.run_command(&mut engine, cmd).unwrap();
+    }
+    assert!(engine.settings.chess960);
+    assert_eq!(board_fen(&engine), "2kr3r/pppppppp/8/8/8/8/PPPPPPPP/R4RK1");
+    xboard.run_command(&mut engine, "new").unwrap();
+    assert!(!engine.settings.chess960);
+}
+
+#[test]
+fn xboard_rejects_unsupported_variants() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut xboard: XBoard = Default::default();
+    assert_eq!(
+        xboard.run_command(&mut engine, "variant crazyhouse"),
+        Err(EngineError::Parse(
+            "unsupported variant crazyhouse".to_string()
+        ))
+    );
+}
+
+#[test]
+fn writes_castles_as_king_takes_rook() {
+    let position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    let castle = parse_uci("e1g1", &position);
+    assert_eq!(to_uci(&position, &castle, false).to_string(), "e1g1");
+    assert_eq!(to_uci(&position, &castle, true).to_string(), "e1h1");
+    assert_eq!(
+        parse_uci_input("e1h1", &position).unwrap(),
+        parse_uci_input("O-O", &position).unwrap()
+    );
+}
@@ -0,0 +1,74 @@
+extern crate deadbeef;
+
+use deadbeef::engine::*;
+use deadbeef::error::*;
+use deadbeef::uci;
+use deadbeef::xboard::{self, *};
+use helpers::*;
+
+mod helpers;
+
+fn run_xboard(cmds: &[&str]) -> (Result<(), EngineError>, String) {
+    setup();
+    let mut engine: Engine = Default::default();
+    engine.reset();
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "force").unwrap();
+    let (last, setup_cmds) = cmds.split_last().unwrap();
+    for cmd in setup_cmds {
+        xboard.run_command(&mut engine, cmd).unwrap();
+    }
+    let result = xboard.run_command(&mut engine, last);
+    let message = match result {
+        Err(ref error) => xboard::error_message(error, last),
+        Ok(_) => String::new(),
+    };
+    (result, message)
+}
+
+#[test]
+fn xboard_reports_illegal_moves() {
+    let (result, message) = run_xboard(&["usermove e2e5"]);
+    assert_eq!(result, Err(EngineError::IllegalMove("e2e5".to_string())));
+    assert_eq!(message, "Illegal move: e2e5");
+    let (_, message) = run_xboard(&["usermove e2e4", "usermove zz"]);
+    assert_eq!(message, "Illegal move: zz");
+}
+
+#[test]
+fn xboard_reports_invalid_positions_to_the_user() {
+    let (result, message) = run_xboard(&["setboard 8/8/8/8/8/8/8/8 w - - 0 1"]);
+    assert!(matches!(result, Err(EngineError::InvalidPosition(_))));
+    assert_eq!(message, "tellusererror Illegal position");
+}
+
+#[test]
+fn xboard_reports_bad_commands() {
+    let (result, message) = run_xboard(&["foo bar"]);
+    assert_eq!(result, Err(EngineError::UnknownCommand));
+    assert_eq!(message, "Error (unknown command): foo bar");
+    let (_, message) = run_xboard(&["sd deep"]);
+    assert_eq!(message, "Error (invalid depth deep): sd deep");
+    let (_, message) = run_xboard(&["undo"]);
+    assert_eq!(message, "Error (no moves to undo): undo");
+    let (result, message) = run_xboard(&["setboard 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", "go"]);
+    assert_eq!(result, Err(EngineError::GameOver));
+    assert_eq!(message, "Error (game is over): go");
+}
+
+#[test]
+fn uci_reports_errors_as_info_strings() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut uci: uci::Uci = Default::default();
+    let result = uci.run_command(&mut engine, "position startpos moves e2e5");
+    assert_eq!(
+        uci::error_message(&result.unwrap_err(), "position startpos moves e2e5"),
+        "info string illegal move e2e5"
+    );
+    let result = uci.run_command(&mut engine, "foo");
+    assert_eq!(
+        uci::error_message(&result.unwrap_err(), "foo"),
+        "info string unknown command foo"
+    );
+}
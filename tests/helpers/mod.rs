@@ -6,6 +6,7 @@ extern crate shakmaty;
 
 use self::log::*;
 use self::shakmaty::*;
+use deadbeef::args::*;
 use deadbeef::engine::*;
 use deadbeef::game::*;
 use deadbeef::logger;
@@ -14,7 +15,9 @@ use deadbeef::settings::*;
 use deadbeef::setup::*;
 use deadbeef::stats::*;
 use deadbeef::utils::*;
-use std::time::Duration;
+use deadbeef::xboard::*;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use std::sync::Once;
 
@@ -26,6 +29,43 @@ pub fn setup() {
     });
 }
 
+// the defaults, without printing the engine's thinking over the test output
+pub fn engine_settings() -> Settings {
+    Settings {
+        show_thinking: false,
+        ..Default::default()
+    }
+}
+
+// for tests that let the engine move, quickly
+pub fn iteration_settings(iterations: u32) -> Settings {
+    Settings {
+        search_type: SearchType::Iterations(iterations),
+        ..engine_settings()
+    }
+}
+
+// an engine at the starting position
+pub fn new_engine(settings: Settings) -> Engine {
+    setup();
+    let mut engine = Engine::new(settings);
+    engine.reset();
+    engine
+}
+
+// polls until the engine has moved, failing the test if it never does
+pub fn wait_for_move(xboard: &mut XBoard, engine: &mut Engine) {
+    let start = Instant::now();
+    while xboard.is_thinking() {
+        assert!(
+            start.elapsed() < Duration::from_secs(60),
+            "no move after 60s"
+        );
+        thread::sleep(Duration::from_millis(10));
+        xboard.poll(engine).unwrap();
+    }
+}
+
 pub fn assert_move(fen_str: &'static str, uci_str: &'static str) -> RunStats {
     let settings = Settings::test_default();
     run_move_test(fen_str, vec![uci_str], vec![], &settings, false)
@@ -157,7 +197,9 @@ fn run_move_test_and_assert(
 
 fn setup_engine(fen_str: &str, settings: &Settings) -> Engine {
     setup();
-    let mut engine = Engine::new(settings.clone());
+    let mut settings = settings.clone();
+    apply_final_move_selection_env(&mut settings);
+    let mut engine = Engine::new(settings);
     engine.set_board(fen_str).unwrap();
     engine
 }
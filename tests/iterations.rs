@@ -1,3 +1,107 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::chess_state::*;
+use deadbeef::mcts::*;
+use deadbeef::settings::*;
+use deadbeef::setup::*;
+use deadbeef::stats::*;
+use deadbeef::tree_node::*;
+use deadbeef::uct::*;
+use deadbeef::utils::*;
+use helpers::*;
+
+mod helpers;
+
+// searches until the result is proven, returning how many iterations that took, and the move.
+// A win is proven before it's known to be the quickest, so this is the first mate found
+fn iterations_to_prove(
+    fen: &str,
+    rave: Option<RaveSchedule>,
+    max_iterations: u32,
+) -> (Option<u32>, String) {
+    setup();
+    let settings = Settings {
+        rave,
+        ..Settings::test_lib_default()
+    };
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(ChessState::new(parse_fen(fen).into()));
+    let mut proven_after = None;
+    for i in 0..max_iterations {
+        if root.proof().is_some() {
+            proven_after = Some(i);
+            break;
+        }
+        root.iteration(&mut rng, &mut stats, &settings);
+    }
+    let best = root.best_child(&settings.final_move_selection).unwrap();
+    (proven_after, best.action.clone().unwrap().to_string())
+}
+
+// searches on past the first proof, to where a quicker mate may have been found, returning the move
+fn best_after(fen: &str, iterations: u32) -> String {
+    setup();
+    let settings = Settings::test_lib_default();
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(ChessState::new(parse_fen(fen).into()));
+    for _ in 0..iterations {
+        if !root.is_searchable() {
+            break;
+        }
+        root.iteration(&mut rng, &mut stats, &settings);
+    }
+    let best = root.best_child(&settings.final_move_selection).unwrap();
+    best.action.clone().unwrap().to_string()
+}
+
+// RAVE against plain UCT at equal iteration budgets. Where the playouts already find the mate,
+// e.g. mates in 3 plies, both prove it in the same few dozen iterations. Deeper, what the
+// playouts learn about a move anywhere in the line pays off
+const RAVE: Option<RaveSchedule> = Some(RaveSchedule::Equivalence(1000.));
+
+#[test]
+fn rave_proves_a_smothered_mate_sooner() {
+    let fen = "3r3k/6pp/3N4/3Q4/8/8/6K1/8 w - - 1 1";
+    let (without, best_without) = iterations_to_prove(fen, None, 10000);
+    let (with, best) = iterations_to_prove(fen, RAVE, 10000);
+    assert_eq!(best_without, "Nd6-f7");
+    assert_eq!(best, "Nd6-f7");
+    // about 950 iterations against 4500
+    assert!(with.unwrap() * 2 < without.unwrap());
+}
+
+#[test]
+fn rave_finds_the_quiet_mating_move() {
+    // Edward Lasker–Sir George Thomas (London 1912). Without RAVE a mate in 9 plies starting
+    // with Ng4-e5 is proven first
+    let fen = "rn3r2/pbppq1p1/1p2pN2/8/3P1kNP/3B4/PPP2PP1/R3K2R w KQ - 1 15";
+    let (with, best) = iterations_to_prove(fen, RAVE, 1000);
+    assert!(with.is_some());
+    assert_eq!(best, "g2-g3");
+}
+
+#[test]
+fn searches_on_for_a_shorter_mate_than_the_first_proven() {
+    // the mate in 9 plies starting with Ng4-e5 is proven first, but the game's is shorter
+    let fen = "rn3r2/pbppq1p1/1p2pN2/8/3P1kNP/3B4/PPP2PP1/R3K2R w KQ - 1 15";
+    let (without, best) = iterations_to_prove(fen, None, 1000);
+    assert!(without.is_some());
+    assert_eq!(best, "Ng4-e5");
+    assert_eq!(best_after(fen, 10000), "g2-g3");
+}
+
+#[test]
+fn rave_makes_no_difference_to_shallow_mates() {
+    let fen = "2r2r1k/p1q3pp/8/3Q1p2/2N5/PP3N2/4n1P1/R1B2n1K b - - 0 1";
+    assert_eq!(
+        iterations_to_prove(fen, None, 500),
+        iterations_to_prove(fen, RAVE, 500)
+    );
+}
+
 // extern crate deadbeef;
 // extern crate shakmaty;
 //
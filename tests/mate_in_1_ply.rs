@@ -13,6 +13,12 @@ fn queen_mate_white_in_1() {
     // assert!(stats.max_depth() == 1);
 }
 
+#[test]
+fn stops_searching_once_mate_is_proven() {
+    let stats = assert_mate_move("4k3/Q7/5K2/8/8/8/8/8 w - - 0 1", "a7e7");
+    assert!(stats.iterations < 100);
+}
+
 #[test]
 fn queen_mate_black_in_1() {
     let stats = assert_mate_move("4K3/q7/5k2/8/8/8/8/8 b - - 0 1", "a7e7");
@@ -0,0 +1,113 @@
+extern crate deadbeef;
+
+use deadbeef::engine::*;
+use deadbeef::search_strategy::*;
+use deadbeef::search_threaded::*;
+use deadbeef::settings::*;
+use deadbeef::table::*;
+use deadbeef::transposition::*;
+use deadbeef::xboard::*;
+use helpers::*;
+use std::sync::Arc;
+
+mod helpers;
+
+#[test]
+fn stops_growing_tree_at_memory_budget() {
+    setup();
+    let mut engine = Engine::new(Settings {
+        search_type: SearchType::Iterations(5000),
+        show_thinking: false,
+        memory_mb: Some(1),
+        ..Default::default()
+    });
+    engine.reset();
+    engine.search_with_settings().unwrap();
+    let root = &engine.state.root;
+    let max_nodes = 1024 * 1024 / shared_node_size(root) as u64;
+    // each iteration adds at most one node per thread, and the root is expanded regardless
+    let slack = engine.settings.max_threads as u64 + root.children.len() as u64;
+    assert!(root.count_nodes() <= max_nodes + slack);
+    // the search goes on through the nodes it has
+    assert_eq!(root.n, 5000);
+}
+
+#[test]
+fn each_thread_grows_its_own_tree_within_memory_budget() {
+    setup();
+    let mut engine = Engine::new(Settings {
+        search_type: SearchType::Iterations(5000),
+        show_thinking: false,
+        memory_mb: Some(1),
+        parallelism: Parallelism::Root,
+        ..Default::default()
+    });
+    engine.reset();
+    engine.search_with_settings().unwrap();
+    let root = &engine.state.root;
+    let max_nodes = 1024 * 1024 / root.estimated_expanded_size() as u64;
+    // only the subtrees searched most are kept, so only the root's children are counted over
+    // the threads
+    let threads = engine.settings.max_threads as u64;
+    assert!(root.count_nodes() <= max_nodes + threads * root.children.len() as u64);
+    assert!(root.n >= 5000);
+}
+
+#[test]
+fn playout_table_comes_out_of_memory_budget() {
+    setup();
+    let mut engine = Engine::new(Settings {
+        search_type: SearchType::Iterations(1000),
+        show_thinking: false,
+        memory_mb: Some(1),
+        playout_table: Some(Arc::new(Table::empty_mb(1))),
+        ..Default::default()
+    });
+    engine.reset();
+    engine.search_with_settings().unwrap();
+    // the tree alone would fit in the budget, so it's only the root's children that it doesn't
+    // grow below because the table took it
+    let root = &engine.state.root;
+    assert!(root.children.iter().all(|c| c.children.is_empty()));
+    assert_eq!(root.n, 1000);
+}
+
+#[test]
+fn transposition_table_comes_out_of_memory_budget() {
+    setup();
+    let mut engine = Engine::new(Settings {
+        search_type: SearchType::Iterations(1000),
+        show_thinking: false,
+        memory_mb: Some(1),
+        transpositions: Some(Arc::new(TranspositionTable::with_mb(1))),
+        ..Default::default()
+    });
+    engine.reset();
+    engine.search_with_settings().unwrap();
+    let root = &engine.state.root;
+    assert!(root.children.iter().all(|c| c.children.is_empty()));
+    assert_eq!(root.n, 1000);
+}
+
+#[test]
+fn xboard_sets_memory_and_cores() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "memory 64").unwrap();
+    xboard.run_command(&mut engine, "cores 2").unwrap();
+    assert_eq!(engine.settings.memory_mb, Some(64));
+    assert_eq!(engine.settings.max_threads, 2);
+    assert!(xboard.run_command(&mut engine, "cores all").is_err());
+}
+
+#[test]
+fn xboard_new_removes_the_depth_limit() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "sd 4").unwrap();
+    assert_eq!(engine.settings.max_depth, Some(4));
+    xboard.run_command(&mut engine, "new").unwrap();
+    assert_eq!(engine.settings.max_depth, None);
+}
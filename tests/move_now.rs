@@ -0,0 +1,53 @@
+extern crate deadbeef;
+
+use deadbeef::xboard::*;
+use helpers::*;
+use std::time::Instant;
+
+mod helpers;
+
+#[test]
+fn move_now_plays_best_move_so_far() {
+    let mut engine = new_engine(engine_settings());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "st 60").unwrap();
+    let start = Instant::now();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    assert!(xboard.is_thinking());
+    xboard.run_command(&mut engine, "ping 1").unwrap();
+    xboard.run_command(&mut engine, "time 6000").unwrap();
+    assert!(xboard.is_thinking());
+    assert_eq!(xboard.pending_pings(), ["1"]);
+    xboard.run_command(&mut engine, "?").unwrap();
+    assert!(!xboard.is_thinking());
+    // answered with the move
+    assert!(xboard.pending_pings().is_empty());
+    assert!(!engine.is_searching_in_background());
+    assert_eq!(engine.history.moves().len(), 2);
+    assert!(start.elapsed().as_secs() < 30);
+}
+
+#[test]
+fn force_stops_thinking_without_moving() {
+    let mut engine = new_engine(engine_settings());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "st 60").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    xboard.run_command(&mut engine, "force").unwrap();
+    assert!(!xboard.is_thinking());
+    assert!(!engine.is_searching_in_background());
+    assert_eq!(engine.history.moves().len(), 1);
+    xboard.run_command(&mut engine, "usermove e7e5").unwrap();
+    assert!(!xboard.is_thinking());
+    assert_eq!(engine.history.moves().len(), 2);
+}
+
+#[test]
+fn timed_search_finishes_on_its_own() {
+    let mut engine = new_engine(engine_settings());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "st 1").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    assert_eq!(engine.history.moves().len(), 2);
+}
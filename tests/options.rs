@@ -0,0 +1,25 @@
+extern crate deadbeef;
+
+use deadbeef::engine::*;
+use deadbeef::xboard::*;
+use helpers::*;
+
+mod helpers;
+
+#[test]
+fn xboard_sets_options() {
+    setup();
+    let mut engine: Engine = Default::default();
+    let mut xboard: XBoard = Default::default();
+    xboard
+        .run_command(&mut engine, "option Exploration x1000=500")
+        .unwrap();
+    xboard
+        .run_command(&mut engine, "option Quiescence Search=0")
+        .unwrap();
+    assert_eq!(engine.settings.c, 0.5);
+    assert!(!engine.settings.q_search);
+    assert!(xboard.run_command(&mut engine, "option Threads=many").is_err());
+    assert!(xboard.run_command(&mut engine, "option Threads").is_err());
+    assert_eq!(engine.settings.max_threads, 4);
+}
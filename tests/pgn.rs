@@ -0,0 +1,80 @@
+extern crate deadbeef;
+
+use deadbeef::settings::*;
+use deadbeef::xboard::*;
+use helpers::*;
+use std::env;
+use std::fs;
+
+mod helpers;
+
+fn settings(pgn_file: &str) -> Settings {
+    Settings {
+        pgn_file: Some(pgn_file.to_string()),
+        ..iteration_settings(20)
+    }
+}
+
+// removed first, in case an earlier run failed before removing it
+fn temp_pgn_file(name: &str) -> String {
+    let pgn_file = env::temp_dir().join(name).to_string_lossy().into_owned();
+    let _ = fs::remove_file(&pgn_file);
+    pgn_file
+}
+
+#[test]
+fn appends_finished_games() {
+    let pgn_file = temp_pgn_file("deadbeef_appends_finished_games.pgn");
+    let mut engine = new_engine(settings(&pgn_file));
+    let mut xboard: XBoard = Default::default();
+    for cmd in [
+        "new",
+        "name Fool",
+        "rating 2100 1500",
+        "computer",
+        "force",
+        "usermove f2f3",
+        "usermove e7e5",
+        "usermove g2g4",
+        "usermove d8h4",
+        "result 0-1 {Black mates}",
+        "new",
+        "setboard 4k3/8/4K3/8/8/8/8/7R b - - 10 40",
+        "result * {aborted}",
+    ]
+    .iter()
+    {
+        xboard.run_command(&mut engine, cmd).unwrap();
+    }
+
+    let pgn = fs::read_to_string(&pgn_file).unwrap();
+    let games: Vec<&str> = pgn.split("[Event ").skip(1).collect();
+    assert_eq!(games.len(), 2);
+    assert!(games[0].contains("[Result \"0-1\"]\n"));
+    assert!(games[0].contains("[Termination \"normal\"]\n"));
+    assert!(games[0].contains("\n1. f3 e5 2. g4 Qh4# {Black mates} 0-1\n"));
+    assert!(games[1].contains("[Result \"*\"]\n"));
+    assert!(games[1].contains("[Termination \"unterminated\"]\n"));
+    assert!(games[1].contains("[FEN \"4k3/8/4K3/8/8/8/8/7R b - - 10 40\"]\n"));
+    fs::remove_file(&pgn_file).unwrap();
+}
+
+#[test]
+fn names_players_by_color() {
+    let pgn_file = temp_pgn_file("deadbeef_names_players_by_color.pgn");
+    let mut engine = new_engine(settings(&pgn_file));
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "new").unwrap();
+    xboard.run_command(&mut engine, "name Human Player").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    xboard.run_command(&mut engine, "result 1-0 {Black resigns}").unwrap();
+
+    let pgn = fs::read_to_string(&pgn_file).unwrap();
+    assert!(pgn.contains("[White \"Human Player\"]\n"));
+    assert!(pgn.contains("[Black \"deadbeef\"]\n"));
+    assert!(pgn.contains("[WhiteType \"human\"]\n"));
+    assert!(pgn.contains("[BlackType \"program\"]\n"));
+    assert!(pgn.contains("\n1. e4 "));
+    fs::remove_file(&pgn_file).unwrap();
+}
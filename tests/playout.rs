@@ -8,7 +8,9 @@ use deadbeef::playout::*;
 use deadbeef::settings::*;
 use deadbeef::setup::*;
 use deadbeef::stats::*;
+use deadbeef::table::*;
 use helpers::*;
+use std::sync::Arc;
 
 mod helpers;
 
@@ -32,7 +34,7 @@ fn mated_black() {
 fn mate_in_1_white() {
     for depth in 1..4 {
         let reward = reward_test("4k3/8/4K3/8/8/8/8/7R w - -", depth);
-        assert!(reward == MAX_REWARD);
+        assert!(reward == MAX_REWARD - 1);
     }
 }
 
@@ -40,7 +42,7 @@ fn mate_in_1_white() {
 fn mate_in_1_black() {
     for depth in 1..4 {
         let reward = reward_test("5r2/8/8/8/8/3k4/8/3K4 b - -", depth);
-        assert!(reward == MIN_REWARD);
+        assert!(reward == MIN_REWARD + 1);
     }
 }
 
@@ -48,7 +50,7 @@ fn mate_in_1_black() {
 fn mate_in_1_and_half_white() {
     for depth in 2..4 {
         let reward = reward_test("3R4/8/8/8/8/1K6/8/k7 b - -", depth);
-        assert!(reward == MAX_REWARD);
+        assert!(reward == MAX_REWARD - 2);
     }
 }
 
@@ -56,14 +58,14 @@ fn mate_in_1_and_half_white() {
 fn mate_in_1_and_half_black() {
     for depth in 2..4 {
         let reward = reward_test("K7/8/1k6/8/8/8/8/3r4 w - -", depth);
-        assert!(reward == MIN_REWARD);
+        assert!(reward == MIN_REWARD + 2);
     }
 }
 
 fn mate_in_2_white() {
     for depth in 3..5 {
         let reward = reward_test("k7/4R3/1K6/8/8/8/8/8 w - -", depth);
-        assert!(reward == MAX_REWARD);
+        assert!(reward == MAX_REWARD - 3);
     }
 }
 
@@ -74,7 +76,7 @@ fn mate_in_2_white_complex() {
             "rn3r2/pbppq1p1/1p2pN2/8/3P2NP/6P1/PPP1BPk1/R3K2R w KQ -",
             depth,
         );
-        assert!(reward == MAX_REWARD);
+        assert!(reward == MAX_REWARD - 3);
     }
 }
 
@@ -82,7 +84,8 @@ fn mate_in_2_white_complex() {
 fn mate_in_2_black() {
     for depth in 3..5 {
         let reward = reward_test("8/8/8/8/8/1k6/4r3/K7 b - -", depth);
-        assert!(reward == MIN_REWARD);
+        // Re1 mates straight away
+        assert!(reward == MIN_REWARD + 1);
     }
 }
 
@@ -90,7 +93,7 @@ fn mate_in_2_black() {
 fn mate_in_2_and_half_white() {
     for depth in 4..5 {
         let reward = reward_test("1k6/7R/2K5/8/8/8/8/8 b - -", depth);
-        assert!(reward == MAX_REWARD);
+        assert!(reward == MAX_REWARD - 4);
     }
 }
 
@@ -98,7 +101,7 @@ fn mate_in_2_and_half_white() {
 fn mate_in_2_and_half_black() {
     for depth in 4..5 {
         let reward = reward_test("8/8/8/8/8/2k5/6r1/1K6 w - -", depth);
-        assert!(reward == MIN_REWARD);
+        assert!(reward == MIN_REWARD + 4);
     }
 }
 
@@ -183,6 +186,42 @@ fn saves_bishop() {
     }
 }
 
+#[test]
+fn returns_the_principal_variation() {
+    setup();
+    let position = parse_fen("4k3/8/4K3/8/8/8/8/7R w - -");
+    let mut stats: RunStats = Default::default();
+    let settings = Settings::playout_test(3, false);
+    let (reward, line) = playout_line(position.clone(), &mut stats, &settings);
+    assert_eq!(reward, MAX_REWARD - 1);
+    assert_eq!(line, vec![parse_uci("h1h8", &position)]);
+}
+
+#[test]
+fn remembers_what_earlier_playouts_found() {
+    setup();
+    let position = parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3");
+    let settings = Settings::playout_test(2, true);
+    let with_table = Settings {
+        playout_table: Some(Arc::new(Table::empty_mb(4))),
+        ..Settings::playout_test(2, true)
+    };
+    let mut stats: RunStats = Default::default();
+    let (reward, line) = playout_line(position.clone(), &mut stats, &settings);
+    let mut first_stats: RunStats = Default::default();
+    let first = playout_line(position.clone(), &mut first_stats, &with_table);
+    assert_eq!(first, (reward, line.clone()));
+    assert!(first_stats.evals < stats.evals);
+    // the whole playout is in the table now
+    let mut second_stats: RunStats = Default::default();
+    let (second_reward, second_line) =
+        playout_line(position.clone(), &mut second_stats, &with_table);
+    assert_eq!(second_reward, reward);
+    // just the move stored, without following the rest of the line through the table
+    assert_eq!(second_line, vec![line[0].clone()]);
+    assert_eq!(second_stats.evals, 0);
+}
+
 // TEST HELPERS
 
 fn reward_test_with_q(fen_str: &'static str, depth: isize) -> Reward {
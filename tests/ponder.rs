@@ -0,0 +1,67 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::xboard::*;
+use helpers::*;
+use shakmaty::uci::Uci;
+use std::thread;
+use std::time::Duration;
+
+mod helpers;
+
+#[test]
+fn ponder_hit_keeps_pondered_subtree() {
+    let mut engine = new_engine(iteration_settings(50));
+    engine.make_user_move("e2e4").unwrap();
+    engine.make_engine_move().unwrap();
+    engine.start_pondering().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    engine.stop_background_search();
+    let reply = engine.best_move();
+    let reply_uci = Uci::from_move(&engine.position(), &reply).to_string();
+    let pondered_n = engine
+        .state
+        .root
+        .children
+        .iter()
+        .find(|c| c.action == Some(reply.clone()))
+        .unwrap()
+        .n;
+
+    engine.start_pondering().unwrap();
+    engine.make_user_move(&reply_uci).unwrap();
+    assert!(!engine.is_searching_in_background());
+    assert_eq!(engine.game_stats.ponder_hits, 1);
+    assert_eq!(engine.game_stats.ponder_misses, 0);
+    assert!(engine.state.root.n >= pondered_n);
+}
+
+#[test]
+fn xboard_ponders_after_moving_when_hard() {
+    let mut engine = new_engine(iteration_settings(50));
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "new").unwrap();
+    xboard.run_command(&mut engine, "hard").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    assert!(engine.is_searching_in_background());
+    xboard.run_command(&mut engine, "time 1000").unwrap();
+    assert!(engine.is_searching_in_background());
+    xboard.run_command(&mut engine, "easy").unwrap();
+    assert!(!engine.is_searching_in_background());
+    assert!(engine.state.time_remaining.is_some());
+}
+
+#[test]
+fn xboard_stops_pondering_for_board_commands() {
+    let mut engine = new_engine(iteration_settings(50));
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "new").unwrap();
+    xboard.run_command(&mut engine, "hard").unwrap();
+    xboard.run_command(&mut engine, "usermove e2e4").unwrap();
+    wait_for_move(&mut xboard, &mut engine);
+    assert!(engine.is_searching_in_background());
+    xboard.run_command(&mut engine, "force").unwrap();
+    assert!(!engine.is_searching_in_background());
+    assert_eq!(engine.history.moves().len(), 2);
+}
@@ -0,0 +1,355 @@
+extern crate deadbeef;
+
+use deadbeef::game::*;
+use deadbeef::game_state::*;
+use deadbeef::mcts::*;
+use deadbeef::move_selection::*;
+use deadbeef::search_strategy::*;
+use deadbeef::search_threaded::*;
+use deadbeef::settings::*;
+use deadbeef::stats::*;
+use deadbeef::tree_node::*;
+use deadbeef::uct::*;
+use deadbeef::utils::*;
+use helpers::*;
+
+mod helpers;
+
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+// the smallest game worth searching, to check the tree doesn't depend on chess
+#[derive(Debug, Clone)]
+struct TicTacToe {
+    squares: [Option<Player>; 9],
+    to_move: Player,
+}
+
+impl Default for TicTacToe {
+    fn default() -> TicTacToe {
+        TicTacToe {
+            squares: [None; 9],
+            to_move: Player::First,
+        }
+    }
+}
+
+impl TicTacToe {
+    // squares numbered 0 to 8 from the top left, X moving first
+    fn from_str(board: &str) -> TicTacToe {
+        let mut game: TicTacToe = Default::default();
+        for (i, c) in board.chars().enumerate() {
+            game.squares[i] = match c {
+                'X' => Some(Player::First),
+                'O' => Some(Player::Second),
+                _ => None,
+            };
+        }
+        let taken = game.squares.iter().filter(|s| s.is_some()).count();
+        game.to_move = if taken % 2 == 0 {
+            Player::First
+        } else {
+            Player::Second
+        };
+        game
+    }
+
+    fn winner(&self) -> Option<Player> {
+        LINES
+            .iter()
+            .map(|line| line.iter().map(|&i| self.squares[i]).collect::<Vec<_>>())
+            .find(|line| line[0].is_some() && line[0] == line[1] && line[1] == line[2])
+            .and_then(|line| line[0])
+    }
+}
+
+impl GameState for TicTacToe {
+    type Action = usize;
+
+    fn legal_actions(&self) -> Vec<usize> {
+        if self.is_terminal() {
+            vec![]
+        } else {
+            (0..9).filter(|&i| self.squares[i].is_none()).collect()
+        }
+    }
+
+    fn apply(&mut self, action: &usize) {
+        self.squares[*action] = Some(self.to_move);
+        self.to_move = !self.to_move;
+    }
+
+    fn player_to_move(&self) -> Player {
+        self.to_move
+    }
+
+    fn terminal_value(&self) -> Option<Reward> {
+        match self.winner() {
+            Some(Player::First) => Some(MAX_REWARD),
+            Some(Player::Second) => Some(MIN_REWARD),
+            None if self.squares.iter().all(|s| s.is_some()) => Some(0),
+            None => None,
+        }
+    }
+
+    fn heuristic_value(&self) -> Reward {
+        0
+    }
+}
+
+fn search(board: &str, iterations: u32) -> TreeNode<TicTacToe> {
+    search_with(board, iterations, &Default::default())
+}
+
+fn search_with(board: &str, iterations: u32, settings: &Settings) -> TreeNode<TicTacToe> {
+    setup();
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root = TreeNode::new_root(TicTacToe::from_str(board));
+    for _ in 0..iterations {
+        if !root.is_searchable() {
+            break;
+        }
+        root.iteration(&mut rng, &mut stats, settings);
+    }
+    root
+}
+
+fn best_action(root: &TreeNode<TicTacToe>) -> usize {
+    let chain = [FinalMoveSelection::Minimax, FinalMoveSelection::RobustChild];
+    root.best_child(&chain).and_then(|c| c.action).unwrap()
+}
+
+#[test]
+fn completes_a_line() {
+    let root = search("XX.OO....", 1000);
+    assert_eq!(best_action(&root), 2);
+    assert_eq!(root.minimax, MAX_REWARD - 1);
+}
+
+#[test]
+fn proves_a_win_without_trying_every_move() {
+    let root = search("..XOOX...", 1000);
+    assert_eq!(root.proof(), Some(Proof::Win));
+    assert_eq!(root.children.len(), 1);
+    assert_eq!(best_action(&root), 8);
+}
+
+#[test]
+fn proves_a_loss_once_every_move_loses() {
+    // X can only stop one of O's lines
+    let root = search("OX.XOXO..", 1000);
+    assert_eq!(root.proof(), Some(Proof::Loss));
+    assert!(!root.is_searchable());
+}
+
+#[test]
+fn prefers_the_quickest_win() {
+    // 6 and 7 fork, but 0 wins on the spot
+    let root = search(".OO.X...X", 1000);
+    assert_eq!(best_action(&root), 0);
+    assert_eq!(root.minimax, MAX_REWARD - 1);
+}
+
+#[test]
+fn proves_a_draw() {
+    // without bounds, every line had to be played out, which took more iterations than this
+    let root = search("X...O....", 1000);
+    assert_eq!(root.proof(), Some(Proof::Draw));
+    assert_eq!((root.pessimistic, root.optimistic), (0, 0));
+}
+
+#[test]
+fn proves_the_empty_board_is_a_draw() {
+    let root = search(".........", 50000);
+    assert_eq!(root.proof(), Some(Proof::Draw));
+}
+
+#[test]
+fn blocks_a_line() {
+    let root = search("XX..O....", 3000);
+    assert_eq!(best_action(&root), 2);
+}
+
+#[test]
+fn finds_a_forced_win() {
+    // an edge reply to a center opening loses
+    let root = search(".O..X....", 20000);
+    assert_eq!(root.minimax, MAX_REWARD - 5);
+    assert_eq!(mate_plies(root.minimax), Some(5));
+}
+
+#[test]
+fn searches_with_puct() {
+    let settings = Settings {
+        selection_formula: SelectionFormula::Puct,
+        ..Default::default()
+    };
+    let root = search_with("XX..O....", 3000, &settings);
+    assert_eq!(best_action(&root), 2);
+    // no square looks better than another before it's searched
+    let priors: Vec<f32> = root.children.iter().map(|c| c.prior).collect();
+    assert!(priors.iter().all(|&p| (p - 1. / 6.).abs() < 1e-5));
+    let root = search_with(".O..X....", 20000, &settings);
+    assert_eq!(root.minimax, MAX_REWARD - 5);
+}
+
+#[test]
+fn widens_progressively() {
+    let settings = Settings {
+        widening: Some(Widening {
+            c: 1.,
+            exponent: 0.5,
+        }),
+        ..Default::default()
+    };
+    // 3 squares are enough for the first 9 visits
+    let root = search_with(".........", 10, &settings);
+    assert_eq!(root.children.len(), 3);
+    assert_eq!(root.state, NodeState::Expandable);
+    let root = search_with(".O..X....", 20000, &settings);
+    assert_eq!(root.minimax, MAX_REWARD - 5);
+    // the root's children too, when searched in threads. None of 4 iterations starts after more
+    // than 3 visits, counting the virtual losses, which allow 2 squares
+    let root = search_in_threads(TreeNode::new_root(Default::default()), 4, &settings);
+    assert_eq!(root.n, 4);
+    assert!(root.children.len() <= 2);
+}
+
+#[test]
+fn learns_all_moves_as_first() {
+    let settings = Settings {
+        rave: Some(RaveSchedule::Equivalence(100.)),
+        ..Default::default()
+    };
+    // each child's own visits count, and some of its siblings' too
+    let root = search_with(".........", 200, &settings);
+    assert!(root.children.iter().all(|c| c.amaf_n >= c.n));
+    assert!(root.children.iter().any(|c| c.amaf_n > c.n));
+    let root = search_in_threads(TreeNode::new_root(Default::default()), 200, &settings);
+    assert!(root.children.iter().all(|c| c.amaf_n >= c.n));
+    assert!(root.children.iter().any(|c| c.amaf_n > c.n));
+}
+
+#[test]
+fn searches_in_threads() {
+    setup();
+    let settings = Settings {
+        max_threads: 2,
+        ..Default::default()
+    };
+    let root = TreeNode::new_root(TicTacToe::from_str("O.XO..X.."));
+    let root = search_in_threads(root, 1000, &settings);
+    assert_eq!(best_action(&root), 4);
+    assert_eq!(root.minimax, MAX_REWARD - 1);
+}
+
+#[test]
+fn spreads_its_threads_over_the_children() {
+    let settings = Settings {
+        max_threads: 4,
+        ..Default::default()
+    };
+    let root = search_in_threads(TreeNode::new_root(Default::default()), 4, &settings);
+    assert_eq!(root.n, 4);
+    assert_eq!(root.children.iter().filter(|c| c.n == 1).count(), 4);
+    // the threads go on searching, until it's solved
+    let pool = SearchPool::new(&settings, Default::default());
+    let mut stats: RunStats = Default::default();
+    let mut root = TreeNode::new_root(TicTacToe::from_str("........."));
+    while root.is_searchable() && pool.is_searching() {
+        root = pool.search(root, &mut stats);
+    }
+    assert_eq!(root.minimax, 0);
+    assert_no_virtual_losses(&pool.finish(root), 0);
+}
+
+// every virtual loss is taken back, so a node's visits are its own playout's and its children's
+fn assert_no_virtual_losses(node: &TreeNode<TicTacToe>, playouts: u32) {
+    if node.children.is_empty() {
+        return;
+    }
+    assert_eq!(
+        node.children.iter().map(|c| c.n).sum::<u32>() + playouts,
+        node.n
+    );
+    for child in &node.children {
+        assert_no_virtual_losses(child, 1);
+    }
+}
+
+#[test]
+fn searches_below_the_root_with_more_threads_than_children() {
+    let settings = Settings {
+        max_threads: 16,
+        ..Default::default()
+    };
+    let root = TreeNode::new_root(TicTacToe::from_str("X........"));
+    let root = search_in_threads(root, 16, &settings);
+    assert_eq!(root.children.len(), 8);
+    assert_eq!(root.n, 16);
+    assert!(root.children.iter().any(|c| !c.children.is_empty()));
+    assert_no_virtual_losses(&root, 0);
+}
+
+#[test]
+fn seeds_threads_past_the_last_seed() {
+    for parallelism in PARALLELISMS.iter() {
+        let settings = Settings {
+            max_threads: 4,
+            starting_seed: 255,
+            parallelism: *parallelism,
+            ..Default::default()
+        };
+        let root = search_in_threads(TreeNode::new_root(Default::default()), 20, &settings);
+        assert!(root.n > 0);
+    }
+}
+
+// as the search strategies do, until the iterations are used up or it's solved
+fn search_in_threads(
+    mut root: TreeNode<TicTacToe>,
+    iterations: u64,
+    settings: &Settings,
+) -> TreeNode<TicTacToe> {
+    let mut stats: RunStats = Default::default();
+    let limits = Limits {
+        iterations: Some(iterations),
+        ..Default::default()
+    };
+    let mut searcher = Searcher::new(&root, settings, limits);
+    while root.is_searchable() && searcher.is_searching(&stats, settings) {
+        root = searcher.search(root, &mut stats);
+    }
+    searcher.finish(root)
+}
+
+#[test]
+fn combines_the_trees_of_a_root_parallel_search() {
+    let settings = Settings {
+        max_threads: 4,
+        parallelism: Parallelism::Root,
+        ..Default::default()
+    };
+    let root = search_in_threads(TreeNode::new_root(Default::default()), 100, &settings);
+    assert_eq!(root.n, 100);
+    assert_eq!(root.children.len(), 9);
+    assert_eq!(root.children.iter().map(|c| c.n).sum::<u32>(), root.n);
+    // each thread's tree only depends on its seed
+    let again = search_in_threads(TreeNode::new_root(Default::default()), 100, &settings);
+    let visits = |root: &TreeNode<TicTacToe>| root.children.iter().map(|c| c.n).collect::<Vec<_>>();
+    assert_eq!(visits(&again), visits(&root));
+    // a tree kept from an earlier search is only counted once
+    let kept = search_in_threads(search(".........", 100), 100, &settings);
+    assert_eq!(kept.n, 200);
+    let solved = search_in_threads(search("X...O....", 100), 4000, &settings);
+    assert_eq!(solved.proof(), Some(Proof::Draw));
+}
@@ -0,0 +1,162 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::chess_state::*;
+use deadbeef::game::*;
+use deadbeef::game_state::*;
+use deadbeef::mcts::*;
+use deadbeef::settings::*;
+use deadbeef::setup::*;
+use deadbeef::stats::*;
+use deadbeef::transposition::*;
+use deadbeef::tree_node::*;
+use deadbeef::utils::*;
+use helpers::*;
+use std::sync::Arc;
+
+mod helpers;
+
+fn settings(transpositions: bool) -> Settings {
+    Settings {
+        transpositions: if transpositions {
+            Some(Arc::new(TranspositionTable::with_mb(16)))
+        } else {
+            None
+        },
+        ..Settings::test_lib_default()
+    }
+}
+
+// searches until the result is proven, returning how many iterations that took and the move
+fn search(
+    game: ChessState,
+    settings: &Settings,
+    max_iterations: u32,
+) -> (Option<u32>, String, RunStats) {
+    setup();
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(game);
+    let mut proven_after = None;
+    for i in 0..max_iterations {
+        if !root.is_searchable() {
+            proven_after = Some(i);
+            break;
+        }
+        root.iteration(&mut rng, &mut stats, settings);
+    }
+    let best = root.best_child(&settings.final_move_selection).unwrap();
+    (
+        proven_after,
+        best.action.clone().unwrap().to_string(),
+        stats,
+    )
+}
+
+fn from_fen(fen: &str) -> ChessState {
+    ChessState::new(parse_fen(fen).into())
+}
+
+#[test]
+fn shares_statistics_between_transpositions() {
+    let (_, _, stats) = search(Default::default(), &settings(true), 3000);
+    assert!(stats.tt_hits > 0);
+    assert!(stats.tt_hit_rate() > 0);
+    let (_, _, stats) = search(Default::default(), &settings(false), 3000);
+    assert_eq!(stats.tt_probes, 0);
+}
+
+#[test]
+fn proves_the_same_mates() {
+    let fen = "2r2r1k/p1q3pp/8/3Q1p2/2N5/PP3N2/4n1P1/R1B2n1K b - - 0 1";
+    let (without, best_without, _) = search(from_fen(fen), &settings(false), 2000);
+    let (with, best_with, stats) = search(from_fen(fen), &settings(true), 2000);
+    assert!(without.is_some());
+    assert!(with.is_some());
+    assert_eq!(best_with, best_without);
+    assert!(stats.tt_probes > 0);
+}
+
+fn assert_children_visited_at_most_as_parent(node: &TreeNode) {
+    let children_n: u32 = node.children.iter().map(|c| c.n).sum();
+    assert!(children_n <= node.n, "{} visits below {}", children_n, node.n);
+    for child in &node.children {
+        assert_children_visited_at_most_as_parent(child);
+    }
+}
+
+#[test]
+fn adopted_visits_are_not_counted_twice() {
+    setup();
+    let settings = settings(true);
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(Default::default());
+    for _ in 0..3000 {
+        root.iteration(&mut rng, &mut stats, &settings);
+    }
+    assert!(stats.tt_hits > 0);
+    assert_children_visited_at_most_as_parent(&root);
+}
+
+#[test]
+fn does_not_share_once_a_position_repeats() {
+    let mut game: ChessState = Default::default();
+    for uci in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+        let action = parse_uci(uci, &game.position);
+        game.apply(&action);
+    }
+    let (_, _, stats) = search(game, &settings(true), 1000);
+    assert_eq!(stats.tt_probes, 0);
+}
+
+fn entry(game: &ChessState, n: u32, q: f32, proven: bool) -> Entry {
+    Entry {
+        key: game.transposition_key().unwrap(),
+        n,
+        q,
+        value: 0,
+        minimax: 0,
+        pessimistic: if proven { 0 } else { MIN_REWARD },
+        optimistic: if proven { 0 } else { MAX_REWARD },
+        proven,
+    }
+}
+
+#[test]
+fn adopts_a_position_proven_elsewhere_as_a_leaf() {
+    setup();
+    let settings = settings(true);
+    let table = settings.transpositions.clone().unwrap();
+    let game: ChessState = Default::default();
+    for action in game.legal_actions() {
+        let mut child = game.clone();
+        child.apply(&action);
+        table.store(entry(&child, 10, 0., true));
+    }
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(game);
+    root.iteration(&mut rng, &mut stats, &settings);
+    let adopted = &root.children[0];
+    assert_eq!(adopted.state, NodeState::LeafNode);
+    assert_eq!((adopted.pessimistic, adopted.optimistic), (0, 0));
+}
+
+#[test]
+fn follows_the_mean_of_a_position_searched_more_elsewhere() {
+    setup();
+    let settings = settings(true);
+    let table = settings.transpositions.clone().unwrap();
+    // Kh7 is the only move
+    let game = from_fen("R6k/8/8/8/8/8/8/6RK b - - 0 1");
+    let mut stats: RunStats = Default::default();
+    let mut rng = seeded_rng(settings.starting_seed);
+    let mut root: TreeNode = TreeNode::new_root(game);
+    root.iteration(&mut rng, &mut stats, &settings);
+    table.store(entry(&root.children[0].game, 1000, 750., false));
+    root.iteration(&mut rng, &mut stats, &settings);
+    let child = &root.children[0];
+    assert_eq!(child.n, 2);
+    assert_eq!(child.q, 1.5);
+}
@@ -0,0 +1,96 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::game::*;
+use deadbeef::search_strategy::*;
+use deadbeef::settings::*;
+use helpers::*;
+use shakmaty::fen::*;
+
+mod helpers;
+
+#[test]
+fn undo_restores_previous_position() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    engine.make_user_move("e2e4").unwrap();
+    engine.make_user_move("e7e5").unwrap();
+    engine.undo().unwrap();
+    assert_eq!(
+        fen(&engine.position()),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert_eq!(engine.history.moves().len(), 1);
+}
+
+#[test]
+fn undo_without_moves_is_an_error() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    assert!(engine.undo().is_err());
+}
+
+#[test]
+fn undo_rolls_back_repetitions() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    for uci_str in &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"] {
+        engine.make_user_move(uci_str).unwrap();
+    }
+    let before_repetition = engine.state.root.game.repetition_detector.clone();
+    engine.make_user_move("f6g8").unwrap();
+    assert!(engine.is_game_over());
+    engine.undo().unwrap();
+    assert!(!engine.is_game_over());
+    assert_eq!(engine.state.root.game.repetition_detector, before_repetition);
+}
+
+#[test]
+fn undo_keeps_searched_tree_as_child() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    engine.make_user_move("e2e4").unwrap();
+    let engine_move = engine.make_engine_move().unwrap();
+    let searched_n = engine.state.root.n;
+    engine.undo().unwrap();
+    assert_eq!(engine.state.root.children.len(), 1);
+    assert_eq!(engine.state.root.children[0].action, Some(engine_move));
+    assert_eq!(engine.state.root.n, searched_n);
+    // and we can keep searching from the restored root
+    engine.search(&SearchType::Iterations(20)).unwrap();
+    assert!(engine.state.root.children.len() > 1);
+}
+
+#[test]
+fn remove_takes_back_a_full_move() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    engine.make_user_move("e2e4").unwrap();
+    engine.make_user_move("e7e5").unwrap();
+    engine.undo_n(2).unwrap();
+    assert_eq!(fen(&engine.position()), STARTING_POSITION);
+    assert!(engine.history.is_empty());
+}
+
+#[test]
+fn remove_after_a_single_move_takes_nothing_back() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    engine.make_user_move("e2e4").unwrap();
+    assert!(engine.undo_n(2).is_err());
+    assert_eq!(
+        fen(&engine.position()),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert_eq!(engine.history.len(), 1);
+}
+
+#[test]
+fn undo_stops_a_search_in_the_background() {
+    let mut engine = new_engine(Settings::test_iteration_default());
+    engine.make_user_move("e2e4").unwrap();
+    engine.start_background_search().unwrap();
+    // the position can still be read while the tree is being searched
+    assert_eq!(
+        fen(&engine.position()),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    engine.undo().unwrap();
+    assert!(!engine.is_searching_in_background());
+    assert_eq!(fen(&engine.position()), STARTING_POSITION);
+    assert_eq!(engine.state.root.children.len(), 1);
+}
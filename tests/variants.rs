@@ -0,0 +1,84 @@
+extern crate deadbeef;
+extern crate shakmaty;
+
+use deadbeef::adjudication::*;
+use deadbeef::engine::*;
+use deadbeef::pgn::*;
+use deadbeef::search_strategy::*;
+use deadbeef::settings::*;
+use deadbeef::variant::*;
+use deadbeef::xboard::*;
+use helpers::*;
+use shakmaty::uci::Uci;
+use shakmaty::*;
+
+mod helpers;
+
+fn set_up(engine: &mut Engine, variant: &str, fen: &str) {
+    let mut xboard: XBoard = Default::default();
+    for cmd in &[
+        "new",
+        &format!("variant {}", variant),
+        "force",
+        &format!("setboard {}", fen),
+    ] {
+        xboard.run_command(engine, cmd).unwrap();
+    }
+}
+
+fn best_move(engine: &mut Engine) -> String {
+    let best_move = engine.test_search(&SearchType::Iterations(300));
+    Uci::from_move(&engine.position(), &best_move).to_string()
+}
+
+#[test]
+fn wins_king_of_the_hill() {
+    let mut engine = new_engine(Settings {
+        max_threads: 1,
+        ..engine_settings()
+    });
+    set_up(
+        &mut engine,
+        "kingofthehill",
+        "4k3/8/8/8/8/3K4/8/8 w - - 0 1",
+    );
+    assert_eq!(engine.position().variant(), Variant::KingOfTheHill);
+    let action = best_move(&mut engine);
+    assert!(["d3d4", "d3e4"].contains(&action.as_str()), "{}", action);
+    engine.make_user_move(&action).unwrap();
+    assert_eq!(claim_result(&engine.state.root), Some("1-0 {White wins}"));
+    assert!(engine
+        .pgn(&Default::default())
+        .contains("[Variant \"King of the Hill\"]\n"));
+}
+
+#[test]
+fn explodes_the_king_in_atomic() {
+    let mut engine = new_engine(Settings {
+        max_threads: 1,
+        ..engine_settings()
+    });
+    set_up(&mut engine, "atomic", "4k3/4p3/8/8/8/8/8/4QK2 w - - 0 1");
+    assert_eq!(best_move(&mut engine), "e1e7");
+}
+
+#[test]
+fn starts_variants_from_their_own_position() {
+    let mut engine = new_engine(engine_settings());
+    let mut xboard: XBoard = Default::default();
+    xboard.run_command(&mut engine, "variant horde").unwrap();
+    assert_eq!(engine.position().board().white().count(), 36);
+    xboard.run_command(&mut engine, "new").unwrap();
+    assert_eq!(engine.position().variant(), Variant::Standard);
+    assert_eq!(engine.position().board().white().count(), 16);
+}
+
+#[test]
+fn stalemate_wins_antichess() {
+    let mut engine = new_engine(engine_settings());
+    // black has no moves, so black wins
+    set_up(&mut engine, "giveaway", "8/8/8/8/8/p7/P7/8 b - - 0 1");
+    assert!(engine.is_game_over());
+    assert_eq!(claim_result(&engine.state.root), Some("0-1 {Black wins}"));
+    assert_eq!(to_pgn(&engine.position(), &[]), "[Result \"0-1\"]\n");
+}